@@ -1,22 +1,28 @@
-use std::{io::{BufReader, Lines, self, BufRead, Write, BufWriter}, fs::File, path::Path};
+use std::{io::{self, BufRead, Write, BufWriter}, fs::File, path::Path};
 
 use crate::error::{Error, Result};
-use crate::{symbol_table::SymbolTable, OutputFormat};
+use crate::{symbol_table::SymbolTable, debug_info::DebugInfo, OutputFormat};
 
 pub struct Assembler {
     st: SymbolTable,
+    debug_info: DebugInfo,
     output_format: OutputFormat,
     save_preprocessed_file: bool,
+    save_debug_info: bool,
     line_num: u16,
+    data: Vec<(String, usize)>,     // Values of the .word directives with their source line
 }
 
 impl Assembler {
-    pub fn new(output_format: OutputFormat, save_preprocessed_file: bool) -> Self {
+    pub fn new(output_format: OutputFormat, save_preprocessed_file: bool, save_debug_info: bool) -> Self {
         Self {
             st: SymbolTable::new(),
+            debug_info: DebugInfo::new(),
             output_format,
             save_preprocessed_file,
+            save_debug_info,
             line_num: 1,
+            data: Vec::new(),
         }
     }
     
@@ -24,6 +30,7 @@ impl Assembler {
         let file = File::open(&input_file)?;
         let lines = io::BufReader::new(file).lines();
         let file_name_no_ext = Path::new(&input_file).file_stem().unwrap().to_str().unwrap();
+        self.debug_info.set_source(&input_file);
 
         let mut preprocessed_lines = self.preprocess(lines)?;
         if self.save_preprocessed_file {
@@ -37,6 +44,17 @@ impl Assembler {
             format!("{}.hack", file_name_no_ext)
         };
 
+        if self.save_debug_info {
+            let dbg_file_name = Path::new(&out_file_name).with_extension("dbg");
+            self.debug_info.write(&dbg_file_name.to_string_lossy())?;
+        }
+
+        let ram_image = self.resolve_data()?;
+        if !ram_image.is_empty() {
+            let ram_file_name = Path::new(&out_file_name).with_extension("ram");
+            self.write_ram_image(&mut BufWriter::new(File::create(ram_file_name)?), &ram_image)?;
+        }
+
         let mut out_buf = BufWriter::<File>::new(File::create(out_file_name)?);
 
         self.assemble(&mut preprocessed_lines, &mut out_buf)
@@ -50,14 +68,15 @@ impl Assembler {
         Ok(())
     }
 
-    fn preprocess(&mut self, lines: Lines<BufReader<File>>) -> Result<Vec<String>> {
+    fn preprocess(&mut self, lines: impl Iterator<Item = io::Result<String>>) -> Result<Vec<String>> {
         let mut preprocessed_lines = Vec::new();
-        let mut line_number = 0 as u16;
+        let mut line_number = 0_u16;
 
-        for line in lines {
-            let pp_line = self.preprocess_line(&line?, &line_number)?;
+        for (source_line, line) in lines.enumerate() {
+            let pp_line = self.preprocess_line(&line?, &line_number, source_line + 1)?;
             if let Some(asm_line) = pp_line {
                 preprocessed_lines.push(asm_line);
+                self.debug_info.add_line(line_number, source_line + 1);
                 line_number += 1;
             }
         }
@@ -65,7 +84,7 @@ impl Assembler {
         Ok(preprocessed_lines)
     }
 
-    fn preprocess_line(&mut self, line: &String, line_number: & u16) -> Result<Option<String>> {
+    fn preprocess_line(&mut self, line: &str, line_number: & u16, source_line: usize) -> Result<Option<String>> {
         // Preprocessed line
         let mut pp_line = String::new();
 
@@ -86,6 +105,11 @@ impl Assembler {
             return Ok(None);
         }
 
+        if pp_line.starts_with('.') {
+            self.preprocess_directive(&pp_line, source_line)?;
+            return Ok(None);
+        }
+
         // Add labels to the symbol table
         if pp_line.starts_with("("){
             if !pp_line.ends_with(")") {
@@ -93,12 +117,58 @@ impl Assembler {
             }
             let label = pp_line[1..pp_line.len()-1].to_string();
             self.st.add_label(&label, *line_number);
+            self.debug_info.add_label(&label, *line_number);
             return Ok(None);
         }
         
         Ok(Some(pp_line))
     }
 
+    // Data directives, reserving RAM words from address 16, before the variables
+    //   .data NAME         label the next data word
+    //   .word V1,V2,...    data words, numbers or symbols defined anywhere in the program
+    fn preprocess_directive(&mut self, line: &str, source_line: usize) -> Result<()> {
+        if let Some(label) = line.strip_prefix(".data") {
+            if label.is_empty() {
+                return Err(Error::PreprocessError(format!("Missing data label name at line {}", source_line)));
+            }
+            if self.st.get_symbol_addr(label).is_some() {
+                return Err(Error::PreprocessError(format!("Data label {} redefines an existing symbol at line {}", label, source_line)));
+            }
+            self.st.add_data_label(label);
+        } else if let Some(values) = line.strip_prefix(".word") {
+            let values: Vec<&str> = values.split(',').collect();
+            if values.iter().any(|value| value.is_empty()) {
+                return Err(Error::PreprocessError(format!("Missing data word value at line {}", source_line)));
+            }
+            self.st.allocate_data(values.len() as u16)?;
+            self.data.extend(values.iter().map(|value| (value.to_string(), source_line)));
+        } else {
+            return Err(Error::PreprocessError(format!("Unknown directive {} at line {}", line, source_line)));
+        }
+        Ok(())
+    }
+
+    // Values of the data words, once every label is known
+    fn resolve_data(&self) -> Result<Vec<u16>> {
+        self.data.iter().map(|(value, source_line)| {
+            let number = match value.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                None => value.parse::<u16>().ok().or_else(|| value.parse::<i16>().ok().map(|value| value as u16)),
+            };
+            number.or_else(|| self.st.get_symbol_addr(value))
+                .ok_or_else(|| Error::AssembleError(format!("Invalid data word {} at line {}", value, source_line)))
+        }).collect()
+    }
+
+    // RAM image of the data words, big-endian like the binary output, starting at address 16
+    fn write_ram_image(&self, out_buf: &mut impl Write, words: &[u16]) -> Result<()> {
+        for word in words {
+            out_buf.write_all(&word.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
     pub fn assemble(&mut self, lines: &mut Vec<String>, out_buf: &mut BufWriter<File>) -> Result<()> {
         
         for line in lines {
@@ -117,14 +187,14 @@ impl Assembler {
             },
             OutputFormat::Binary => {
                 let instr_bytes = instr.to_be_bytes();
-                out_buf.write(&instr_bytes)?;
+                out_buf.write_all(&instr_bytes)?;
             }
         }
         Ok(())
     }
 
 
-    fn assemble_line(&mut self, line: &String) -> Result<u16> {
+    fn assemble_line(&mut self, line: &str) -> Result<u16> {
         if line.starts_with("@") {
             Ok(self.assemble_a_instr(line)?)
         } else {
@@ -132,10 +202,10 @@ impl Assembler {
         }
     }
 
-    fn assemble_a_instr(&mut self, line: &String) -> Result<u16> {
+    fn assemble_a_instr(&mut self, line: &str) -> Result<u16> {
         let mut is_symbol = false;
         
-        let mut ram_addr = 0 as u16;
+        let mut ram_addr = 0_u16;
         let mut symbol = String::new();
 
         if line.len() == 1 {
@@ -144,7 +214,7 @@ impl Assembler {
 
         // Get the value or symbol
         for c in line.chars().skip(1) {
-            if !is_symbol && c.is_digit(10) {
+            if !is_symbol && c.is_ascii_digit() {
                 ram_addr = ram_addr * 10 + c.to_digit(10).unwrap() as u16;
             } else {
                 is_symbol = true;
@@ -172,8 +242,8 @@ impl Assembler {
         let mut cursor_idx = 0;
         
         let dest_bits = self.get_dest_bits(&mut cursor_idx, line)?;
-        let comp_bits = self.get_comp_bits(&mut cursor_idx, &line)?;
-        let jump_bits = self.get_jump_bits(cursor_idx, &line)?;
+        let comp_bits = self.get_comp_bits(&mut cursor_idx, line)?;
+        let jump_bits = self.get_jump_bits(cursor_idx, line)?;
 
         Ok(0b111 << 13 | comp_bits << 6 | dest_bits << 3 | jump_bits)
     }
@@ -257,4 +327,57 @@ impl Assembler {
             _ => return Err(Error::AssembleError(format!("Invalid jump at line {}", self.line_num)))
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(source: &str) -> Result<Assembler> {
+        let mut assembler = Assembler::new(OutputFormat::Binary, false, false);
+        assembler.preprocess(source.lines().map(|line| Ok(line.to_string())))?;
+        Ok(assembler)
+    }
+
+    #[test]
+    fn data_words_follow_each_other_from_address_16() {
+        let source = "
+            .data TABLE
+            .word 1, 2, 3
+            .data MESSAGE
+            .word 0x41,-1,TABLE,END   // Symbols defined anywhere
+            @TABLE
+            (END)
+            @counter
+        ";
+        let mut assembler = preprocess(source).unwrap();
+        assert_eq!(assembler.st.get_symbol_addr("TABLE"), Some(16));
+        assert_eq!(assembler.st.get_symbol_addr("MESSAGE"), Some(19));
+        assert_eq!(assembler.resolve_data().unwrap(), [1, 2, 3, 0x41, 0xFFFF, 16, 1]);
+        // Variables come after the data
+        assert_eq!(assembler.assemble_a_instr("@counter").unwrap(), 23);
+    }
+
+    #[test]
+    fn invalid_data_words() {
+        assert!(matches!(preprocess(".word 1,,2"), Err(Error::PreprocessError(_))));
+        assert!(matches!(preprocess(".data"), Err(Error::PreprocessError(_))));
+        assert!(matches!(preprocess(".byte 1"), Err(Error::PreprocessError(_))));
+        assert!(matches!(preprocess(".word UNDEFINED").unwrap().resolve_data(), Err(Error::AssembleError(_))));
+        assert!(matches!(preprocess(".word 65536").unwrap().resolve_data(), Err(Error::AssembleError(_))));
+    }
+
+    #[test]
+    fn ram_image_is_big_endian_words() {
+        let assembler = preprocess(".word 1, 0x1234, -1").unwrap();
+        let mut bytes = Vec::new();
+        assembler.write_ram_image(&mut bytes, &assembler.resolve_data().unwrap()).unwrap();
+        assert_eq!(bytes, [0x00, 0x01, 0x12, 0x34, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn data_label_redefining_a_symbol_is_an_error() {
+        for source in [".data SP", ".data R15", "(LOOP)\n.data LOOP", ".data TABLE\n.word 1\n.data TABLE"] {
+            assert!(matches!(preprocess(source), Err(Error::PreprocessError(_))), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use crate::error::Result;
+
+// Debug information saved next to the compiled output, used by the emulator tooling
+// Text format, one record per line:
+//   source <path>                      assembly file the program was compiled from
+//   label <rom_address> <name>
+//   line <rom_address> <source_line>   source line of each instruction, 1-based
+pub struct DebugInfo {
+    source: String,
+    labels: Vec<(u16, String)>,
+    lines: Vec<(u16, usize)>,
+}
+
+impl DebugInfo {
+    pub fn new() -> Self {
+        Self {
+            source: String::new(),
+            labels: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn set_source(&mut self, source: &str) {
+        self.source = source.to_string();
+    }
+
+    pub fn add_line(&mut self, rom_address: u16, source_line: usize) {
+        self.lines.push((rom_address, source_line));
+    }
+
+    pub fn add_label(&mut self, label: &str, rom_address: u16) {
+        self.labels.push((rom_address, label.to_string()));
+    }
+
+    pub fn write(&self, file_name: &str) -> Result<()> {
+        let mut out_buf = BufWriter::new(File::create(file_name)?);
+        writeln!(out_buf, "source {}", self.source)?;
+        for (rom_address, label) in &self.labels {
+            writeln!(out_buf, "label {} {}", rom_address, label)?;
+        }
+        for (rom_address, source_line) in &self.lines {
+            writeln!(out_buf, "line {} {}", rom_address, source_line)?;
+        }
+        Ok(())
+    }
+}
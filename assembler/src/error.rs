@@ -2,6 +2,7 @@ use core::fmt;
 use std::{io, result};
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     IoError(io::Error),
     PreprocessError(String),
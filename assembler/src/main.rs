@@ -1,6 +1,7 @@
 use clap::Parser;
 
 mod symbol_table;
+mod debug_info;
 mod assembler;
 mod error;
 
@@ -16,6 +17,8 @@ struct Cli {
     output_format: OutputFormat,
     #[clap(short='i', long="save-pp-file", help = "Set this flag to save the intermediate preprocessed file")]
     save_preprocessed_file: bool,
+    #[clap(short='g', long="debug-info", help = "Set this flag to save debug information (labels, source lines) to a .dbg file next to the output")]
+    save_debug_info: bool,
 }
 
 #[derive(clap::ValueEnum, Clone)]
@@ -26,7 +29,7 @@ pub enum OutputFormat {
 
 fn main() {
     let cli = Cli::parse();
-    if cli.input_file == "" {
+    if cli.input_file.is_empty() {
         println!("No input file specified");
     }
 
@@ -38,7 +41,7 @@ fn main() {
     }
 
     // Instantiate the assembler
-    let mut assembler = Assembler::new(cli.output_format, cli.save_preprocessed_file);
+    let mut assembler = Assembler::new(cli.output_format, cli.save_preprocessed_file, cli.save_debug_info);
     match assembler.run(cli.input_file, cli.output) {
         Ok(_) => println!("Assembled successfully"),
         Err(e) => println!("{}", e),
@@ -8,6 +8,8 @@ pub struct SymbolTable {
 }
 
 impl SymbolTable {
+    pub const RAM_START: u16 = 16;     // First RAM address of the data and variables
+
     // Adds a symbol table entry given a symbol and its address
     // If the address is None, add the symbol to the symbol table and increment the RAM address
     // If the address is Some, add the symbol to the symbol table with the given address
@@ -41,7 +43,7 @@ impl SymbolTable {
 
         Self {
             symbols,
-            ram_addr: 16,
+            ram_addr: Self::RAM_START,
         }
     }
 
@@ -62,6 +64,21 @@ impl SymbolTable {
         Ok(self.ram_addr - 1)
     }
 
+    // Add a data label at the next free RAM address
+    pub fn add_data_label(&mut self, label: &str) {
+        self.symbols.insert(label.to_string(), self.ram_addr);
+    }
+
+    // Reserve RAM words for data, before any variable is allocated
+    // Returns the address of the first word
+    pub fn allocate_data(&mut self, size: u16) -> Result<u16, Error> {
+        if self.ram_addr as usize + size as usize > 16384 {
+            return Err(Error::SymbolTableError("Data does not fit in RAM".to_string()));
+        }
+        self.ram_addr += size;
+        Ok(self.ram_addr - size)
+    }
+
     // Get the address of a symbol
     // Return None if the symbol is not in the symbol table
     pub fn get_symbol_addr(&self, symbol: &str) -> Option<u16> {
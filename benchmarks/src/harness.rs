@@ -0,0 +1,114 @@
+use std::{fs, time::{Duration, Instant}, collections::HashMap};
+
+use hardware_emulator::{Result, Error};
+
+// A measured operation: `run` does one iteration and returns the time it took, setup excluded
+pub struct Benchmark {
+    pub name: &'static str,
+    pub unit: &'static str,     // What an iteration processes, for the throughput
+    pub elements: u64,          // Units processed per iteration
+    pub run: Box<dyn FnMut() -> Result<Duration>>,
+}
+
+// Timing of a benchmark, per iteration
+pub struct Measurement {
+    pub name: String,
+    pub samples: usize,
+    pub iterations: u64,        // Per sample
+    pub median: f64,            // Nanoseconds
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    pub elements: u64,
+}
+
+impl Measurement {
+    // Units per second, from the median time
+    pub fn throughput(&self) -> f64 {
+        self.elements as f64 / self.median * 1e9
+    }
+}
+
+// Runs benchmarks like criterion does: a warm-up giving the iteration time, then samples of
+// as many iterations as fit the measurement time, whose per-iteration times are summarized
+pub struct Harness {
+    pub warm_up: Duration,
+    pub measurement: Duration,
+    pub samples: usize,
+}
+
+impl Harness {
+    pub fn measure(&self, benchmark: &mut Benchmark) -> Result<Measurement> {
+        let start = Instant::now();
+        let (mut warm_up_time, mut warm_up_iterations) = (Duration::ZERO, 0_u32);
+        while start.elapsed() < self.warm_up || warm_up_iterations == 0 {
+            warm_up_time += (benchmark.run)()?;
+            warm_up_iterations += 1;
+        }
+
+        let iteration_time = warm_up_time.as_secs_f64() / warm_up_iterations as f64;
+        let sample_time = self.measurement.as_secs_f64() / self.samples as f64;
+        let iterations = ((sample_time / iteration_time) as u64).max(1);
+
+        let mut times = Vec::with_capacity(self.samples);
+        for _ in 0..self.samples {
+            let mut total = Duration::ZERO;
+            for _ in 0..iterations {
+                total += (benchmark.run)()?;
+            }
+            times.push(total.as_nanos() as f64 / iterations as f64);
+        }
+        times.sort_by(f64::total_cmp);
+
+        Ok(Measurement {
+            name: benchmark.name.to_string(),
+            samples: self.samples,
+            iterations,
+            median: times[times.len() / 2],
+            min: times[0],
+            max: times[times.len() - 1],
+            unit: benchmark.unit.to_string(),
+            elements: benchmark.elements,
+        })
+    }
+}
+
+// Results file, one CSV line per benchmark with the times in nanoseconds per iteration
+pub fn save_results(path: &str, measurements: &[Measurement]) -> Result<()> {
+    let mut text = String::from("name,samples,iterations,median_ns,min_ns,max_ns,unit,elements\n");
+    for m in measurements {
+        text += &format!("{},{},{},{:.1},{:.1},{:.1},{},{}\n", m.name, m.samples, m.iterations, m.median, m.min, m.max, m.unit, m.elements);
+    }
+    fs::write(path, text)?;
+    println!("Results saved to {}", path);
+    Ok(())
+}
+
+// Median times per iteration of a results file
+pub fn load_baseline(path: &str) -> Result<HashMap<String, f64>> {
+    let mut baseline = HashMap::new();
+    for (index, line) in fs::read_to_string(path)?.lines().enumerate().skip(1) {
+        let fields: Vec<&str> = line.split(',').collect();
+        let median = fields.get(3).and_then(|median| median.parse::<f64>().ok())
+            .ok_or_else(|| Error::ConfigError(format!("{}:{}: expected a results line", path, index + 1)))?;
+        baseline.insert(fields[0].to_string(), median);
+    }
+    Ok(baseline)
+}
+
+pub fn format_time(nanoseconds: f64) -> String {
+    match nanoseconds {
+        t if t < 1e3 => format!("{:.2} ns", t),
+        t if t < 1e6 => format!("{:.2} µs", t / 1e3),
+        t if t < 1e9 => format!("{:.2} ms", t / 1e6),
+        t => format!("{:.3} s", t / 1e9),
+    }
+}
+
+pub fn format_throughput(per_second: f64, unit: &str) -> String {
+    match per_second {
+        t if t < 1e3 => format!("{:.2} {}/s", t, unit),
+        t if t < 1e6 => format!("{:.2} K{}/s", t / 1e3, unit),
+        t => format!("{:.2} M{}/s", t / 1e6, unit),
+    }
+}
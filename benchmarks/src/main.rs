@@ -0,0 +1,80 @@
+use std::{process, time::Duration};
+
+use clap::Parser;
+use hardware_emulator::Result;
+
+mod harness;
+use harness::*;
+
+mod suite;
+
+#[derive(Parser)]
+#[command(author = "Obeeron", version = env!("CARGO_PKG_VERSION"), about = "Performance benchmarks of the Hack emulator")]
+struct Cli {
+    #[clap(long, default_value = concat!(env!("CARGO_MANIFEST_DIR"), "/../hardware_emulator/examples/Pong.hack"), help = "Program run by the CPU and headless benchmarks")]
+    program: String,
+    #[clap(long, default_value_t = 1_000_000, help = "Instructions executed by each headless run")]
+    cycles: u64,
+    #[clap(long, help = "Only run the benchmarks whose name contains this text")]
+    filter: Option<String>,
+    #[clap(long, default_value_t = 20, help = "Samples taken per benchmark")]
+    samples: usize,
+    #[clap(long = "measurement-time", value_name = "SECONDS", default_value_t = 3.0, help = "Time spent measuring each benchmark")]
+    measurement_time: f64,
+    #[clap(long = "warm-up-time", value_name = "SECONDS", default_value_t = 1.0, help = "Time spent running each benchmark before measuring it")]
+    warm_up_time: f64,
+    #[clap(long, value_name = "FILE", help = "Save the results to a CSV file")]
+    save: Option<String>,
+    #[clap(long, value_name = "FILE", help = "Compare the results with a CSV file saved by an earlier run, failing on regressions")]
+    baseline: Option<String>,
+    #[clap(long, value_name = "PERCENT", default_value_t = 5.0, help = "Median time increase over the baseline reported as a regression")]
+    threshold: f64,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let harness = Harness {
+        warm_up: Duration::from_secs_f64(cli.warm_up_time),
+        measurement: Duration::from_secs_f64(cli.measurement_time),
+        samples: cli.samples.max(1),
+    };
+    let baseline = match &cli.baseline {
+        Some(path) => Some(load_baseline(path)?),
+        None => None,
+    };
+
+    let mut measurements = Vec::new();
+    let mut regressions = 0;
+    for mut benchmark in suite::benchmarks(&cli.program, cli.cycles)? {
+        if cli.filter.as_ref().is_some_and(|filter| !benchmark.name.contains(filter.as_str())) {
+            continue;
+        }
+
+        let m = harness.measure(&mut benchmark)?;
+        println!("{:<28} time: [{} {} {}]  thrpt: {}", m.name, format_time(m.min), format_time(m.median), format_time(m.max),
+            format_throughput(m.throughput(), &m.unit));
+
+        if let Some(previous) = baseline.as_ref().and_then(|baseline| baseline.get(&m.name)) {
+            let change = (m.median / previous - 1.0) * 100.0;
+            let verdict = if change > cli.threshold {
+                regressions += 1;
+                "regressed"
+            } else if change < -cli.threshold {
+                "improved"
+            } else {
+                "no change"
+            };
+            println!("{:<28} change: {:+.2}% ({})", "", change, verdict);
+        }
+        measurements.push(m);
+    }
+
+    if let Some(path) = &cli.save {
+        save_results(path, &measurements)?;
+    }
+    if regressions > 0 {
+        println!("{} benchmark(s) regressed by more than {}%", regressions, cli.threshold);
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::{hint::black_box, time::Instant};
+
+use hardware_emulator::{Result, Cpu, Memory, Rom, Emulator, Rasterizer, DisplayOptions, Scaling, Filter};
+
+use crate::harness::Benchmark;
+
+// Instructions run by an iteration of the CPU benchmark
+const CPU_BATCH: u64 = 100_000;
+
+// Benchmarks of the emulator building blocks and of whole headless runs of `program`
+pub fn benchmarks(program: &str, cycles: u64) -> Result<Vec<Benchmark>> {
+    let rom = Rom::from_file(program)?;
+    Ok(vec![
+        rom_from_file(program.to_string(), rom.size() as u64),
+        cpu_execute(rom.clone()),
+        screen_update("screen_update_full_2x", 1024, 512, Scaling::Integer, Filter::Nearest, true),
+        screen_update("screen_update_idle_2x", 1024, 512, Scaling::Integer, Filter::Nearest, false),
+        screen_update("screen_update_smooth_1080p", 1920, 1080, Scaling::Fit, Filter::Smooth, true),
+        headless("headless_interpreter", rom.clone(), cycles, false),
+        headless("headless_fast_engine", rom, cycles, true),
+    ])
+}
+
+// Time to load the ROM file
+fn rom_from_file(program: String, size: u64) -> Benchmark {
+    Benchmark {
+        name: "rom_from_file",
+        unit: "words",
+        elements: size,
+        run: Box::new(move || {
+            let start = Instant::now();
+            black_box(Rom::from_file(&program)?);
+            Ok(start.elapsed())
+        }),
+    }
+}
+
+// Fetch, decode and execute with the reference CPU, without the emulator around it, the
+// machine going on from one iteration to the next
+fn cpu_execute(rom: Rom) -> Benchmark {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    Benchmark {
+        name: "cpu_execute",
+        unit: "instructions",
+        elements: CPU_BATCH,
+        run: Box::new(move || {
+            let start = Instant::now();
+            for _ in 0..CPU_BATCH {
+                let instruction = rom.fetch(cpu.pc)?;
+                let output = cpu.execute(instruction, &mut memory)?;
+                if output.write_to_ram {
+                    memory.store(output.memory_address, output.alu_out)?;
+                }
+            }
+            Ok(start.elapsed())
+        }),
+    }
+}
+
+// Drawing a frame of the screen memory map, every row changing from one frame to the next
+// when `changing`, none otherwise
+fn screen_update(name: &'static str, width: u32, height: u32, scaling: Scaling, filter: Filter, changing: bool) -> Benchmark {
+    let options = DisplayOptions { scaling, filter, ..DisplayOptions::default() };
+    let mut rasterizer = Rasterizer::new(options, width, height);
+    let mut frame = vec![0_u8; width as usize * height as usize * 4];
+    let mut memory = Memory::new();
+    let mut pattern = 0x5555_u16;
+    Benchmark {
+        name,
+        unit: "frames",
+        elements: 1,
+        run: Box::new(move || {
+            if changing {
+                pattern = !pattern;
+                memory.screen_mut().fill(pattern);
+            }
+            let start = Instant::now();
+            black_box(rasterizer.update(&memory, &mut frame));
+            Ok(start.elapsed())
+        }),
+    }
+}
+
+// Whole headless run from the start of the program, emulator setup excluded
+fn headless(name: &'static str, rom: Rom, cycles: u64, fast: bool) -> Benchmark {
+    Benchmark {
+        name,
+        unit: "instructions",
+        elements: cycles,
+        run: Box::new(move || {
+            let mut emulator = Emulator::new(rom.clone(), None, String::new());
+            emulator.max_cycles = Some(cycles);
+            if fast {
+                emulator.enable_fast_engine();
+            }
+            let start = Instant::now();
+            emulator.run_to_end()?;
+            Ok(start.elapsed())
+        }),
+    }
+}
@@ -0,0 +1,214 @@
+use std::{fs::File, io::{BufWriter, Seek, SeekFrom, Write}};
+
+use crate::{Result, Error, Device};
+
+// Square-wave tone generator, two registers:
+//   0  frequency in Hz, 0 for silence
+//   1  volume, from 0 to 255
+//
+// With an output file, the tone is rendered in emulated time to a WAV file, which works
+// headless and whatever the host speed. Without one, it is played live on the default
+// output device, when built with the `audio` feature.
+pub struct Audio {
+    frequency: u16,
+    volume: u16,
+    output: AudioOutput,
+}
+
+enum AudioOutput {
+    Wav(WavWriter),
+    #[cfg(feature = "audio")]
+    Live(live::LiveTone),
+}
+
+impl Audio {
+    pub const SAMPLE_RATE: u32 = 44100;
+    const MAX_VOLUME: u16 = 255;
+
+    pub fn new(wav_path: Option<&str>, cpu_cycles_per_second: u32) -> Result<Self> {
+        let output = match wav_path {
+            Some(path) => AudioOutput::Wav(WavWriter::new(path, cpu_cycles_per_second)?),
+            None => Self::live_output()?,
+        };
+        Ok(Self { frequency: 0, volume: 0, output })
+    }
+
+    #[cfg(feature = "audio")]
+    fn live_output() -> Result<AudioOutput> {
+        Ok(AudioOutput::Live(live::LiveTone::new()?))
+    }
+
+    #[cfg(not(feature = "audio"))]
+    fn live_output() -> Result<AudioOutput> {
+        Err(Error::DeviceError("Live audio requires building with --features audio, give a WAV file like audio@0x6020:out.wav".to_string()))
+    }
+
+    // Peak amplitude, as a fraction of full scale
+    fn amplitude(volume: u16) -> f32 {
+        const MAX_AMPLITUDE: f32 = 0.25;
+        volume.min(Self::MAX_VOLUME) as f32 / Self::MAX_VOLUME as f32 * MAX_AMPLITUDE
+    }
+}
+
+impl Device for Audio {
+    fn name(&self) -> &'static str {
+        "audio"
+    }
+
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&mut self, offset: usize, _cycle: u64) -> u16 {
+        if offset == 0 { self.frequency } else { self.volume }
+    }
+
+    fn write(&mut self, offset: usize, value: u16, cycle: u64) {
+        // The tone so far is rendered with the settings it was played with
+        match &mut self.output {
+            AudioOutput::Wav(wav) => wav.render(cycle, self.frequency, self.volume),
+            #[cfg(feature = "audio")]
+            AudioOutput::Live(_) => {}
+        }
+
+        if offset == 0 {
+            self.frequency = value;
+        } else {
+            self.volume = value;
+        }
+
+        match &mut self.output {
+            AudioOutput::Wav(_) => {}
+            #[cfg(feature = "audio")]
+            AudioOutput::Live(tone) => tone.set(self.frequency, self.volume),
+        }
+    }
+
+    fn shutdown(&mut self, cycle: u64) -> Result<()> {
+        match &mut self.output {
+            AudioOutput::Wav(wav) => {
+                wav.render(cycle, self.frequency, self.volume);
+                wav.finish()
+            }
+            #[cfg(feature = "audio")]
+            AudioOutput::Live(_) => Ok(()),
+        }
+    }
+}
+
+// Mono 16-bit PCM WAV file, written as the emulated time advances
+struct WavWriter {
+    path: String,
+    file: BufWriter<File>,
+    cpu_cycles_per_second: u64,
+    samples: u64,       // Samples written so far
+    phase: f32,         // Position in the square wave period, from 0 to 1
+    error: Option<Error>,
+}
+
+impl WavWriter {
+    const HEADER_SIZE: u32 = 44;
+
+    fn new(path: &str, cpu_cycles_per_second: u32) -> Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        Self::write_header(&mut file, 0)?;
+        Ok(Self {
+            path: path.to_string(),
+            file,
+            cpu_cycles_per_second: cpu_cycles_per_second.max(1) as u64,
+            samples: 0,
+            phase: 0.0,
+            error: None,
+        })
+    }
+
+    fn write_header(out: &mut impl Write, data_size: u32) -> Result<()> {
+        out.write_all(b"RIFF")?;
+        out.write_all(&(Self::HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16_u32.to_le_bytes())?;                  // Format chunk size
+        out.write_all(&1_u16.to_le_bytes())?;                   // PCM
+        out.write_all(&1_u16.to_le_bytes())?;                   // Mono
+        out.write_all(&Audio::SAMPLE_RATE.to_le_bytes())?;
+        out.write_all(&(Audio::SAMPLE_RATE * 2).to_le_bytes())?; // Bytes per second
+        out.write_all(&2_u16.to_le_bytes())?;                   // Bytes per frame
+        out.write_all(&16_u16.to_le_bytes())?;                  // Bits per sample
+        out.write_all(b"data")?;
+        out.write_all(&data_size.to_le_bytes())?;
+        Ok(())
+    }
+
+    // Add the samples up to the given cycle, keeping the first error for finish()
+    fn render(&mut self, cycle: u64, frequency: u16, volume: u16) {
+        let end = cycle * Audio::SAMPLE_RATE as u64 / self.cpu_cycles_per_second;
+        let step = frequency as f32 / Audio::SAMPLE_RATE as f32;
+        let amplitude = (Audio::amplitude(volume) * i16::MAX as f32) as i16;
+
+        while self.samples < end && self.error.is_none() {
+            let sample = if frequency == 0 { 0 } else if self.phase < 0.5 { amplitude } else { -amplitude };
+            if let Err(e) = self.file.write_all(&sample.to_le_bytes()) {
+                self.error = Some(e.into());
+            }
+            self.phase = (self.phase + step).fract();
+            self.samples += 1;
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        let data_size = (self.samples * 2) as u32;
+        self.file.seek(SeekFrom::Start(0))?;
+        Self::write_header(&mut self.file, data_size)?;
+        self.file.flush()?;
+        println!("Audio written to {} ({:.1}s)", self.path, self.samples as f64 / Audio::SAMPLE_RATE as f64);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "audio")]
+mod live {
+    use std::sync::{Arc, atomic::{AtomicU32, Ordering}};
+
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    use crate::{Result, Error, Audio};
+
+    // Tone played on the default output device, following the registers in real time
+    pub struct LiveTone {
+        settings: Arc<AtomicU32>,   // Frequency in the high 16 bits, volume in the low ones
+        _stream: cpal::Stream,
+    }
+
+    impl LiveTone {
+        pub fn new() -> Result<Self> {
+            let error = |e: &dyn std::fmt::Display| Error::DeviceError(format!("Audio output: {}", e));
+            let device = cpal::default_host().default_output_device().ok_or_else(|| error(&"no output device"))?;
+            let config: cpal::StreamConfig = device.default_output_config().map_err(|e| error(&e))?.into();
+            let sample_rate = config.sample_rate.0 as f32;
+            let channels = config.channels as usize;
+
+            let settings = Arc::new(AtomicU32::new(0));
+            let shared = settings.clone();
+            let mut phase = 0.0_f32;
+            let stream = device.build_output_stream(&config, move |data: &mut [f32], _| {
+                let settings = shared.load(Ordering::Relaxed);
+                let (frequency, volume) = ((settings >> 16) as u16, settings as u16);
+                let amplitude = if frequency == 0 { 0.0 } else { Audio::amplitude(volume) };
+                for frame in data.chunks_mut(channels) {
+                    let sample = if phase < 0.5 { amplitude } else { -amplitude };
+                    frame.iter_mut().for_each(|value| *value = sample);
+                    phase = (phase + frequency as f32 / sample_rate).fract();
+                }
+            }, |e| println!("Audio output error: {}", e), None).map_err(|e| error(&e))?;
+            stream.play().map_err(|e| error(&e))?;
+
+            Ok(Self { settings, _stream: stream })
+        }
+
+        pub fn set(&self, frequency: u16, volume: u16) {
+            self.settings.store((frequency as u32) << 16 | volume as u32, Ordering::Relaxed);
+        }
+    }
+}
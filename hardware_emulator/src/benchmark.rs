@@ -0,0 +1,38 @@
+use std::time::Instant;
+
+use crate::{Result, Emulator, Rom, Snapshot};
+
+// Run the same program with the interpreter and the fast engine, and compare their speed and final state
+pub fn run_benchmark(hack_file: &str, cycles: u64) -> Result<()> {
+    let mut snapshots = Vec::new();
+    let mut durations = Vec::new();
+
+    for (name, fast) in [("interpreter", false), ("fast engine", true)] {
+        let mut emulator = Emulator::new(Rom::from_file(hack_file)?, None, String::new());
+        emulator.max_cycles = Some(cycles);
+        if fast {
+            emulator.enable_fast_engine();
+        }
+
+        let start = Instant::now();
+        let result = emulator.run_to_end();
+        let elapsed = start.elapsed().as_secs_f64();
+
+        println!("{:<12} {:>12} instructions in {:>8.3}s, {:>10.2} MIPS", name, emulator.cycles, elapsed,
+            emulator.cycles as f64 / elapsed / 1_000_000.0);
+        if let Err(e) = result {
+            println!("{:<12} stopped on error: {}", "", e);
+        }
+
+        snapshots.push(Snapshot::capture(&emulator.cpu, &emulator.memory));
+        durations.push(elapsed);
+    }
+
+    println!("Speedup: {:.2}x", durations[0] / durations[1]);
+    if snapshots[0] == snapshots[1] {
+        println!("Final states match");
+    } else {
+        println!("Final states differ");
+    }
+    Ok(())
+}
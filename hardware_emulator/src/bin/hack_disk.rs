@@ -0,0 +1,103 @@
+use std::{fs, io};
+
+use clap::{Parser, Subcommand};
+use hardware_emulator::DiskImage;
+
+#[derive(Parser)]
+#[command(author = "Obeeron", version = env!("CARGO_PKG_VERSION"), about = "Create and inspect disk images for the Hack emulator disk device")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Create a blank disk image")]
+    Create {
+        image: String,
+        #[clap(short, long, default_value = "256", help = "Number of sectors of 256 words")]
+        sectors: u64,
+    },
+    #[command(about = "Show the size of an image and which sectors hold data")]
+    Info {
+        image: String,
+    },
+    #[command(about = "Copy a host file into consecutive sectors, one byte per word")]
+    Import {
+        image: String,
+        sector: u64,
+        file: String,
+    },
+    #[command(about = "Print the words of a sector in hexadecimal, with their low bytes as ASCII")]
+    Dump {
+        image: String,
+        sector: u64,
+    },
+}
+
+const WORDS_PER_LINE: usize = 16;
+
+fn main() -> io::Result<()> {
+    match Cli::parse().command {
+        Command::Create { image, sectors } => {
+            DiskImage::create(&image, sectors)?;
+            println!("Created {} with {} sectors ({} bytes)", image, sectors, sectors * DiskImage::SECTOR_BYTES);
+        }
+        Command::Info { image: path } => {
+            let mut image = DiskImage::open(&path)?;
+            println!("{}: {} sectors of {} words ({} bytes)", path, image.sectors(), DiskImage::SECTOR_WORDS, image.sectors() * DiskImage::SECTOR_BYTES);
+
+            let mut words = [0; DiskImage::SECTOR_WORDS];
+            let mut used = Vec::new();
+            for sector in 0..image.sectors() {
+                image.read_sector(sector, &mut words)?;
+                if words.iter().any(|word| *word != 0) {
+                    used.push(sector);
+                }
+            }
+            println!("{} sectors hold data{}", used.len(), if used.is_empty() { String::new() } else { format!(": {}", ranges(&used)) });
+        }
+        Command::Import { image: path, sector, file } => {
+            let mut image = DiskImage::open(&path)?;
+            let bytes = fs::read(&file)?;
+            let mut count = 0;
+            for (index, chunk) in bytes.chunks(DiskImage::SECTOR_WORDS).enumerate() {
+                let mut words = [0; DiskImage::SECTOR_WORDS];
+                for (word, byte) in words.iter_mut().zip(chunk) {
+                    *word = *byte as u16;
+                }
+                image.write_sector(sector + index as u64, &words)?;
+                count += 1;
+            }
+            println!("Imported {} bytes of {} into sectors {}-{}", bytes.len(), file, sector, sector + count.max(1) - 1);
+        }
+        Command::Dump { image: path, sector } => {
+            let mut image = DiskImage::open(&path)?;
+            let mut words = [0; DiskImage::SECTOR_WORDS];
+            image.read_sector(sector, &mut words)?;
+
+            for (line, chunk) in words.chunks(WORDS_PER_LINE).enumerate() {
+                let hex: Vec<String> = chunk.iter().map(|word| format!("{:04X}", word)).collect();
+                let text: String = chunk.iter().map(|word| match *word {
+                    0x20..=0x7E => *word as u8 as char,
+                    _ => '.',
+                }).collect();
+                println!("{:3}: {}  {}", line * WORDS_PER_LINE, hex.join(" "), text);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sorted sector numbers as "0-3, 7, 9-10"
+fn ranges(sectors: &[u64]) -> String {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 1..=sectors.len() {
+        if i == sectors.len() || sectors[i] != sectors[i - 1] + 1 {
+            parts.push(if start == i - 1 { sectors[start].to_string() } else { format!("{}-{}", sectors[start], sectors[i - 1]) });
+            start = i;
+        }
+    }
+    parts.join(", ")
+}
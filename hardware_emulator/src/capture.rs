@@ -0,0 +1,196 @@
+use std::{borrow::Cow, fs::File, io::{BufWriter, Write}};
+
+use crate::{Result, Memory, Palette, Screen};
+
+// Pixels of the screen memory map row by row, true for a set bit
+pub fn screen_pixels(memory: &Memory) -> impl Iterator<Item = bool> + '_ {
+    memory.screen().iter()
+        .flat_map(|word| (0..16).map(move |bit| word & (1 << bit) != 0))
+}
+
+// Write the 512x256 screen as an RGB PNG image
+pub fn save_screenshot(path: &str, memory: &Memory, palette: Palette) -> Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), Screen::WIDTH as u32, Screen::HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = screen_pixels(memory)
+        .flat_map(|set| if set { palette.on } else { palette.off })
+        .collect();
+    encoder.write_header()?.write_image_data(&data)?;
+    println!("Screenshot saved to {}", path);
+    Ok(())
+}
+
+enum RecordingOutput {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Raw(BufWriter<File>),       // 1 bit per pixel, most significant bit leftmost, 1 for a set pixel
+}
+
+// Frame times of a recording, frame n being due at cycle start + n * cpu_hz / fps, so that
+// the recording keeps to wall-clock time whatever the rounding of a single frame
+struct FrameSchedule {
+    start_cycle: u64,
+    cpu_cycles_per_second: u64,
+    frames_per_second: u64,
+    next: u64,              // Index of the next frame
+}
+
+impl FrameSchedule {
+    fn new(cpu_cycles_per_second: u32, frames_per_second: u32, start_cycle: u64) -> Self {
+        Self {
+            start_cycle,
+            cpu_cycles_per_second: cpu_cycles_per_second as u64,
+            frames_per_second: frames_per_second.max(1) as u64,
+            next: 0,
+        }
+    }
+
+    fn cycle(&self, frame: u64) -> u64 {
+        self.start_cycle + frame * self.cpu_cycles_per_second / self.frames_per_second
+    }
+
+    // Time of a frame since the start, in hundredths of a second, the unit of GIF delays
+    fn centiseconds(&self, frame: u64) -> u64 {
+        (frame * 100 + self.frames_per_second / 2) / self.frames_per_second
+    }
+
+    fn next_cycle(&self) -> u64 {
+        self.cycle(self.next)
+    }
+
+    // Take the frame due at `cycles`, skipping the ones missed before it, and give how long
+    // it is shown in hundredths of a second
+    fn advance(&mut self, cycles: u64) -> u64 {
+        let frame = self.next;
+        self.next += 1;
+        while self.cycle(self.next) <= cycles {
+            self.next += 1;
+        }
+        self.centiseconds(self.next) - self.centiseconds(frame)
+    }
+}
+
+// Records the screen at a fixed emulated frame rate, as an animated GIF when the path
+// ends with .gif, and as a raw frame sequence otherwise
+pub struct Recorder {
+    path: String,
+    output: RecordingOutput,
+    schedule: FrameSchedule,
+    frames: u64,
+}
+
+impl Recorder {
+    pub fn new(path: String, palette: Palette, cpu_cycles_per_second: u32, frames_per_second: u32, start_cycle: u64) -> Result<Self> {
+        let file = BufWriter::new(File::create(&path)?);
+        let output = if path.ends_with(".gif") {
+            let global_palette = [palette.off, palette.on].concat();
+            let mut encoder = gif::Encoder::new(file, Screen::WIDTH as u16, Screen::HEIGHT as u16, &global_palette)?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+            RecordingOutput::Gif(encoder)
+        } else {
+            RecordingOutput::Raw(file)
+        };
+
+        println!("Recording to {}", path);
+        Ok(Self {
+            path,
+            output,
+            schedule: FrameSchedule::new(cpu_cycles_per_second, frames_per_second, start_cycle),
+            frames: 0,
+        })
+    }
+
+    // Cycle of the next frame
+    pub fn next_frame(&self) -> u64 {
+        self.schedule.next_cycle()
+    }
+
+    // Add a frame if one is due at this cycle
+    pub fn capture(&mut self, memory: &Memory, cycles: u64) -> Result<()> {
+        if cycles < self.schedule.next_cycle() {
+            return Ok(());
+        }
+
+        let delay = self.schedule.advance(cycles);
+        match &mut self.output {
+            RecordingOutput::Gif(encoder) => {
+                // A frame shown for 0 hundredths of a second, at high frame rates, is merged into the next one
+                if delay == 0 {
+                    return Ok(());
+                }
+                let frame = gif::Frame {
+                    width: Screen::WIDTH as u16,
+                    height: Screen::HEIGHT as u16,
+                    delay: delay.min(u16::MAX as u64) as u16,
+                    buffer: Cow::Owned(screen_pixels(memory).map(|set| set as u8).collect()),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame)?;
+            }
+            RecordingOutput::Raw(file) => {
+                for word in memory.screen().iter() {
+                    file.write_all(&[(*word as u8).reverse_bits(), ((*word >> 8) as u8).reverse_bits()])?;
+                }
+            }
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.output {
+            RecordingOutput::Gif(encoder) => encoder.into_inner()?.flush()?,
+            RecordingOutput::Raw(mut file) => file.flush()?,
+        }
+        println!("Recorded {} frames to {}", self.frames, self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_keep_to_the_clock() {
+        // 1 MHz at 30 fps: 33333.33 cycles per frame
+        let schedule = FrameSchedule::new(1_000_000, 30, 500);
+        assert_eq!(schedule.next_cycle(), 500);
+        assert_eq!(schedule.cycle(1), 500 + 33_333);
+        assert_eq!(schedule.cycle(2), 500 + 66_666);
+        assert_eq!(schedule.cycle(30), 500 + 1_000_000);
+        assert_eq!(schedule.cycle(30 * 3600), 500 + 3600 * 1_000_000);
+    }
+
+    #[test]
+    fn delays_add_up_to_the_elapsed_time() {
+        let mut schedule = FrameSchedule::new(1_000_000, 30, 0);
+        let delays: Vec<u64> = (0..30).map(|_| {
+            let cycle = schedule.next_cycle();
+            schedule.advance(cycle)
+        }).collect();
+        assert!(delays.iter().all(|delay| *delay == 3 || *delay == 4));
+        assert_eq!(delays.iter().sum::<u64>(), 100);
+        assert_eq!(schedule.next_cycle(), 1_000_000);
+    }
+
+    #[test]
+    fn late_capture_skips_the_missed_frames() {
+        // 100 cycles per frame: a capture at 350 takes frame 0, shown until frame 4 at 400
+        let mut schedule = FrameSchedule::new(2500, 25, 0);
+        assert_eq!(schedule.advance(350), 16);
+        assert_eq!(schedule.next_cycle(), 400);
+        assert_eq!(schedule.advance(400), 4);
+        assert_eq!(schedule.next_cycle(), 500);
+    }
+
+    #[test]
+    fn clock_slower_than_the_frame_rate() {
+        // 10 Hz at 25 fps: several frames fall on the same cycle and are taken as one
+        let mut schedule = FrameSchedule::new(10, 25, 0);
+        assert_eq!(schedule.advance(0), 12);
+        assert_eq!(schedule.next_cycle(), 1);
+    }
+}
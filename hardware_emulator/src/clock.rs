@@ -0,0 +1,80 @@
+use std::time::Instant;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SpeedMode {
+    Normal,
+    Turbo,          // As many instructions as the host can run
+    Paused,
+    SlowMotion,     // Target clock rate divided by SLOW_MOTION_FACTOR
+}
+
+// Emulated clock, deciding how many instructions to run for each rendered frame
+// and measuring the clock rate actually achieved
+pub struct Clock {
+    pub target_hz: u32,
+    pub mode: SpeedMode,
+    pub effective_hz: f64,      // Measured over the last MEASURE_PERIOD seconds
+    pending_cycles: f64,        // Fractional instructions carried to the next frame
+    measure_start: Instant,
+    measure_cycles: u64,
+}
+
+impl Clock {
+    pub const FRAMES_PER_SECOND: u32 = 60;
+    const SLOW_MOTION_FACTOR: f64 = 10.0;
+    const MEASURE_PERIOD: f64 = 0.5;
+
+    pub fn new(target_hz: u32) -> Self {
+        Self {
+            target_hz,
+            mode: SpeedMode::Normal,
+            effective_hz: 0.0,
+            pending_cycles: 0.0,
+            measure_start: Instant::now(),
+            measure_cycles: 0,
+        }
+    }
+
+    // Instructions to run during a frame lasting `frame_time` seconds, None for no limit
+    pub fn cycles_for_frame(&mut self, frame_time: f64) -> Option<u64> {
+        let hz = match self.mode {
+            SpeedMode::Normal => self.target_hz as f64,
+            SpeedMode::SlowMotion => self.target_hz as f64 / Self::SLOW_MOTION_FACTOR,
+            SpeedMode::Paused => 0.0,
+            SpeedMode::Turbo => return None,
+        };
+        self.pending_cycles += hz * frame_time;
+        let cycles = self.pending_cycles.floor();
+        self.pending_cycles -= cycles;
+        Some(cycles as u64)
+    }
+
+    // Switch to the given mode, or back to normal speed if it is already active
+    pub fn toggle(&mut self, mode: SpeedMode) {
+        self.mode = if self.mode == mode { SpeedMode::Normal } else { mode };
+        self.pending_cycles = 0.0;
+    }
+
+    // Account for executed instructions, returns true when the effective rate was updated
+    pub fn record(&mut self, cycles: u64) -> bool {
+        self.measure_cycles += cycles;
+        let elapsed = self.measure_start.elapsed().as_secs_f64();
+        if elapsed < Self::MEASURE_PERIOD {
+            return false;
+        }
+        self.effective_hz = self.measure_cycles as f64 / elapsed;
+        self.measure_start = Instant::now();
+        self.measure_cycles = 0;
+        true
+    }
+
+    pub fn status(&self) -> String {
+        let mode = match self.mode {
+            SpeedMode::Normal => "",
+            SpeedMode::Turbo => " [turbo]",
+            SpeedMode::Paused => " [paused]",
+            SpeedMode::SlowMotion => " [slow motion]",
+        };
+        format!("{:.2} MHz (target {:.2} MHz){}", self.effective_hz / 1e6, self.target_hz as f64 / 1e6, mode)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use crate::{Result, Error, DebugInfo, Rom, CpuOutput};
+
+// Records which ROM addresses ran, and which directions each conditional jump took
+pub struct Coverage {
+    output_path: String,
+    hits: Vec<u64>,
+    branches: Vec<[u64; 2]>,    // Taken and not taken counts per ROM address
+}
+
+impl Coverage {
+    pub fn new(output_path: String) -> Self {
+        Self {
+            output_path,
+            hits: vec![0; Rom::ROM_SIZE],
+            branches: vec![[0; 2]; Rom::ROM_SIZE],
+        }
+    }
+
+    pub fn record(&mut self, pc: usize, instruction: u16, cpu_output: &CpuOutput) {
+        self.hits[pc] += 1;
+        if Self::is_conditional_jump(instruction) {
+            let direction = if cpu_output.jump_taken { 0 } else { 1 };
+            self.branches[pc][direction] += 1;
+        }
+    }
+
+    // C instruction whose jump bits are neither null nor JMP
+    fn is_conditional_jump(instruction: u16) -> bool {
+        let jump = instruction & 0b111;
+        instruction >> 15 == 1 && jump != 0b000 && jump != 0b111
+    }
+
+    // Write an lcov tracefile, mapping ROM addresses back to the assembly source lines
+    pub fn write_lcov(&self, rom: &Rom, debug_info: &DebugInfo) -> Result<()> {
+        let source = match &debug_info.source {
+            Some(source) if debug_info.program_size() > 0 => source,
+            _ => return Err(Error::DebugInfoError("Coverage requires debug information with source lines, assemble with -g".to_string())),
+        };
+
+        let mut out = BufWriter::new(File::create(&self.output_path)?);
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", source)?;
+
+        let mut functions: Vec<_> = debug_info.functions()
+            .filter_map(|(address, name)| Some((debug_info.source_line(address)?, address, name)))
+            .collect();
+        functions.sort_unstable();
+        for (line, _, name) in &functions {
+            writeln!(out, "FN:{},{}", line, name)?;
+        }
+        for (_, address, name) in &functions {
+            writeln!(out, "FNDA:{},{}", self.hits[*address as usize], name)?;
+        }
+        writeln!(out, "FNF:{}", functions.len())?;
+        writeln!(out, "FNH:{}", functions.iter().filter(|(_, address, _)| self.hits[*address as usize] != 0).count())?;
+
+        let (mut lines_hit, mut branches_found, mut branches_hit) = (0, 0, 0);
+        for address in 0..debug_info.program_size() {
+            let line = debug_info.source_line(address as u16).unwrap();
+            let hits = self.hits[address];
+            if hits != 0 {
+                lines_hit += 1;
+            }
+
+            if Self::is_conditional_jump(rom.fetch(address)?) {
+                for (direction, count) in self.branches[address].iter().enumerate() {
+                    branches_found += 1;
+                    if hits == 0 {
+                        writeln!(out, "BRDA:{},0,{},-", line, direction)?;
+                    } else {
+                        writeln!(out, "BRDA:{},0,{},{}", line, direction, count)?;
+                    }
+                    if *count != 0 {
+                        branches_hit += 1;
+                    }
+                }
+            }
+            writeln!(out, "DA:{},{}", line, hits)?;
+        }
+
+        writeln!(out, "BRF:{}", branches_found)?;
+        writeln!(out, "BRH:{}", branches_hit)?;
+        writeln!(out, "LF:{}", debug_info.program_size())?;
+        writeln!(out, "LH:{}", lines_hit)?;
+        writeln!(out, "end_of_record")?;
+
+        println!("Coverage written to {} ({}/{} instructions, {}/{} branches)", self.output_path,
+            lines_hit, debug_info.program_size(), branches_hit, branches_found);
+        Ok(())
+    }
+}
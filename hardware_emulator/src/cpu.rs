@@ -10,6 +10,13 @@ pub struct CpuOutput {
     pub write_to_ram: bool,
     pub alu_out: u16,
     pub memory_address: usize,
+    pub jump_taken: bool,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cpu {
@@ -29,7 +36,7 @@ impl Cpu {
         self.pc = 0;
     }
 
-    pub fn execute(&mut self, instruction: u16, memory: &Memory) -> Result<CpuOutput> {
+    pub fn execute(&mut self, instruction: u16, memory: &mut Memory) -> Result<CpuOutput> {
         let first_bit = instruction >> 15;
         
         if first_bit == 0 {
@@ -47,10 +54,11 @@ impl Cpu {
             write_to_ram: false,
             alu_out: 0,
             memory_address: 0,
+            jump_taken: false,
         })
     }
 
-    fn execute_c_instruction(&mut self, instruction: u16, memory: &Memory) -> Result<CpuOutput> {
+    fn execute_c_instruction(&mut self, instruction: u16, memory: &mut Memory) -> Result<CpuOutput> {
         if instruction >> 13 != 0b111 {
             return Err(Error::InvalidCInstructionPadding(self.pc));
         }
@@ -84,7 +92,8 @@ impl Cpu {
         let ng = (alu_out >> 15) & 0b1 != 0;
         let zr = alu_out == 0;
 
-        if (jump & 0b001 != 0 && !ng && !zr) || (jump & 0b010 != 0 && zr) || (jump & 0b100 != 0 && ng) {
+        let jump_taken = (jump & 0b001 != 0 && !ng && !zr) || (jump & 0b010 != 0 && zr) || (jump & 0b100 != 0 && ng);
+        if jump_taken {
             self.pc = self.reg_a as usize;
         } else {
             self.increment_pc();
@@ -94,6 +103,7 @@ impl Cpu {
             write_to_ram,
             alu_out,
             memory_address,
+            jump_taken,
         })
     }
 
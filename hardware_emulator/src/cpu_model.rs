@@ -0,0 +1,281 @@
+use std::{str::FromStr, collections::HashMap, cmp::Reverse};
+
+use crate::{Result, Error, Cpu, CpuOutput, Memory, DebugInfo, Bus};
+
+// Timing model of the CPU: how many clock cycles the executed instructions take
+//
+// Models execute every instruction with the reference `Cpu`, so registers, memory and the
+// PC are the same whatever the model, only the cycle count differs.
+pub trait CpuModel {
+    fn name(&self) -> String;
+
+    // Whether the model must see every instruction, which rules out the fast engine
+    fn needs_interpreter(&self) -> bool;
+
+    fn execute(&mut self, cpu: &mut Cpu, instruction: u16, memory: &mut Memory) -> Result<CpuOutput>;
+
+    // Clock cycles taken by the instructions executed since start
+    fn cycles(&self) -> u64;
+
+    fn report(&self, instructions: u64, debug_info: &DebugInfo);
+}
+
+// CPU model selected on the command line
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CpuModelKind {
+    SingleCycle,
+    Pipeline(usize),    // Number of stages
+}
+
+impl CpuModelKind {
+    pub fn create(self) -> Result<Box<dyn CpuModel>> {
+        Ok(match self {
+            Self::SingleCycle => Box::new(SingleCycle::default()),
+            Self::Pipeline(stages) => Box::new(Pipeline::new(stages)?),
+        })
+    }
+}
+
+impl FromStr for CpuModelKind {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, stages) = match text.split_once(':') {
+            Some((name, stages)) => (name, Some(stages)),
+            None => (text, None),
+        };
+        match (name, stages) {
+            ("single-cycle", None) => Ok(Self::SingleCycle),
+            ("pipeline", None) => Ok(Self::Pipeline(Pipeline::DEFAULT_STAGES)),
+            ("pipeline", Some(stages)) => match stages.parse::<usize>() {
+                Ok(stages) if Pipeline::LAYOUTS.iter().any(|layout| layout.names.len() == stages) => Ok(Self::Pipeline(stages)),
+                _ => Err(format!("Invalid pipeline stages '{}', expected 2 to 5", stages)),
+            },
+            _ => Err(format!("Invalid CPU model '{}', expected single-cycle or pipeline[:STAGES]", text)),
+        }
+    }
+}
+
+// The idealized Hack CPU, running every instruction in one clock cycle
+#[derive(Default)]
+pub struct SingleCycle {
+    instructions: u64,
+}
+
+impl CpuModel for SingleCycle {
+    fn name(&self) -> String {
+        "single-cycle".to_string()
+    }
+
+    fn needs_interpreter(&self) -> bool {
+        false
+    }
+
+    fn execute(&mut self, cpu: &mut Cpu, instruction: u16, memory: &mut Memory) -> Result<CpuOutput> {
+        let output = cpu.execute(instruction, memory)?;
+        self.instructions += 1;
+        Ok(output)
+    }
+
+    fn cycles(&self) -> u64 {
+        self.instructions
+    }
+
+    fn report(&self, instructions: u64, _debug_info: &DebugInfo) {
+        println!("CPU model: {}", self.name());
+        println!("  {:<22} {:>12}", "Instructions", instructions);
+        println!("  {:<22} {:>12}   CPI 1.00", "Cycles", self.cycles());
+    }
+}
+
+// Stages of a pipeline depth where an instruction reads its registers, reads M, resolves
+// its jump and writes its results
+struct Layout {
+    names: &'static [&'static str],
+    read: usize,
+    memory: usize,
+    execute: usize,
+    write_back: usize,
+}
+
+// Register, or RAM word, whose pending write stalled an instruction
+#[derive(Clone, Copy)]
+enum Hazard {
+    A,
+    D,
+    M,
+}
+
+// Cause of the cycles an instruction lost behind the one ahead of it
+#[derive(Clone, Copy)]
+enum Delay {
+    Flush,
+    Hazard(Hazard),
+}
+
+// In-order pipeline without forwarding, fetching one instruction per cycle
+//
+// An instruction reads A and D in its read stage and M in its memory stage, and each of
+// them must have been written back by the instructions ahead. Hazards are detected in the
+// read stage, which holds the instruction, and the ones behind it, until its values are
+// ready. Writes happen in the first half of a cycle and reads in the second, so a value
+// written back is read in the same cycle. Jumps are predicted not taken: a taken jump is
+// resolved in the execute stage and flushes the instructions fetched behind it.
+//
+// The `@LABEL` before a jump writes the target to A, so the jump waits for A: this
+// dependency is the most frequent hazard of Hack programs.
+pub struct Pipeline {
+    layout: &'static Layout,
+    read_time: Option<u64>,         // Cycle in which the last instruction was in the read stage
+    fetch_ready: u64,               // First cycle the next instruction can be fetched in
+    ready_a: u64,                   // First cycle each value can be read in
+    ready_d: u64,
+    ready_ram: Vec<u64>,
+    stalls: [u64; 3],               // Stall cycles per hazard
+    jump_stalls: u64,               // Stall cycles of jumps waiting for A
+    taken_jumps: u64,
+    flushed: u64,                   // Cycles lost to taken jumps
+    stall_sites: HashMap<usize, u64>,
+}
+
+impl Pipeline {
+    pub const DEFAULT_STAGES: usize = 5;
+    const LAYOUTS: [Layout; 4] = [
+        Layout { names: &["IF", "EX"], read: 1, memory: 1, execute: 1, write_back: 1 },
+        Layout { names: &["IF", "ID", "EX"], read: 1, memory: 2, execute: 2, write_back: 2 },
+        Layout { names: &["IF", "ID", "EX", "WB"], read: 1, memory: 2, execute: 2, write_back: 3 },
+        Layout { names: &["IF", "ID", "MEM", "EX", "WB"], read: 1, memory: 2, execute: 3, write_back: 4 },
+    ];
+    const REPORT_ROWS: usize = 10;
+
+    pub fn new(stages: usize) -> Result<Self> {
+        let layout = Self::LAYOUTS.iter().find(|layout| layout.names.len() == stages)
+            .ok_or_else(|| Error::ConfigError(format!("Unsupported pipeline depth {}, expected 2 to 5", stages)))?;
+        Ok(Self {
+            layout,
+            read_time: None,
+            fetch_ready: 0,
+            ready_a: 0,
+            ready_d: 0,
+            ready_ram: vec![0; Bus::END],
+            stalls: [0; 3],
+            jump_stalls: 0,
+            taken_jumps: 0,
+            flushed: 0,
+            stall_sites: HashMap::new(),
+        })
+    }
+
+    // Moves the instruction at `pc` through the pipeline, given the values it reads and writes
+    fn schedule(&mut self, pc: usize, instruction: u16, address: usize, output: &CpuOutput) {
+        let layout = self.layout;
+        let is_c = instruction >> 15 == 1;
+        let jump = is_c && instruction & 0b111 != 0;
+        let reads_y = is_c && instruction & 0x0200 == 0;
+        let reads_m = reads_y && instruction & 0x1000 != 0;
+        let reads_d = is_c && instruction & 0x0800 == 0;
+        let reads_a = reads_y || output.write_to_ram || jump;
+        let writes_d = is_c && instruction & 0b010000 != 0;
+        let writes_a = !is_c || instruction & 0b100000 != 0;
+
+        // Cycle in which the instruction reaches the read stage without stalling, and the
+        // cycles needed by its fetch after a taken jump and by the values it reads
+        let base = self.read_time.map_or(layout.read as u64, |time| time + 1);
+        let mut constraints = vec![(Delay::Flush, self.fetch_ready + layout.read as u64)];
+        if reads_a {
+            constraints.push((Delay::Hazard(Hazard::A), self.ready_a));
+        }
+        if reads_d {
+            constraints.push((Delay::Hazard(Hazard::D), self.ready_d));
+        }
+        if reads_m {
+            let memory_offset = (layout.memory - layout.read) as u64;
+            let ready = self.ready_ram.get(address).copied().unwrap_or(0);
+            constraints.push((Delay::Hazard(Hazard::M), ready.saturating_sub(memory_offset)));
+        }
+
+        let mut read_time = base;
+        if let Some(&(delay, time)) = constraints.iter().max_by_key(|(_, time)| *time) {
+            if time > base {
+                let lost = time - base;
+                match delay {
+                    Delay::Flush => self.flushed += lost,
+                    Delay::Hazard(hazard) => {
+                        self.stalls[hazard as usize] += lost;
+                        if jump && matches!(hazard, Hazard::A) {
+                            self.jump_stalls += lost;
+                        }
+                        *self.stall_sites.entry(pc).or_insert(0) += lost;
+                    }
+                }
+                read_time = time;
+            }
+        }
+
+        let stage_time = |stage: usize| read_time + (stage - layout.read) as u64;
+        if writes_a {
+            self.ready_a = stage_time(layout.write_back);
+        }
+        if writes_d {
+            self.ready_d = stage_time(layout.write_back);
+        }
+        // Addresses past the bus are left to the store, which reports them as invalid
+        if let Some(ready) = self.ready_ram.get_mut(address).filter(|_| output.write_to_ram) {
+            *ready = stage_time(layout.write_back);
+        }
+        if output.jump_taken {
+            self.taken_jumps += 1;
+            self.fetch_ready = stage_time(layout.execute) + 1;
+        }
+        self.read_time = Some(read_time);
+    }
+}
+
+impl CpuModel for Pipeline {
+    fn name(&self) -> String {
+        format!("{}-stage pipeline ({})", self.layout.names.len(), self.layout.names.join(" "))
+    }
+
+    fn needs_interpreter(&self) -> bool {
+        true
+    }
+
+    fn execute(&mut self, cpu: &mut Cpu, instruction: u16, memory: &mut Memory) -> Result<CpuOutput> {
+        let pc = cpu.pc;
+        let address = cpu.reg_a as usize;
+        let output = cpu.execute(instruction, memory)?;
+        self.schedule(pc, instruction, address, &output);
+        Ok(output)
+    }
+
+    fn cycles(&self) -> u64 {
+        let drain = (self.layout.names.len() - self.layout.read) as u64;
+        self.read_time.map_or(0, |time| time + drain)
+    }
+
+    fn report(&self, instructions: u64, debug_info: &DebugInfo) {
+        let cycles = self.cycles();
+        let cpi = if instructions == 0 { 0.0 } else { cycles as f64 / instructions as f64 };
+        let fill = if instructions == 0 { 0 } else { self.layout.names.len() as u64 - 1 };
+        let stalls: u64 = self.stalls.iter().sum();
+
+        println!("CPU model: {}", self.name());
+        println!("  {:<22} {:>12}", "Instructions", instructions);
+        println!("  {:<22} {:>12}   CPI {:.2}", "Cycles", cycles, cpi);
+        println!("  {:<22} {:>12}", "Pipeline fill", fill);
+        println!("  {:<22} {:>12}", "Data hazard stalls", stalls);
+        println!("    {:<20} {:>12}   {} before a jump", "waiting for A", self.stalls[Hazard::A as usize], self.jump_stalls);
+        println!("    {:<20} {:>12}", "waiting for D", self.stalls[Hazard::D as usize]);
+        println!("    {:<20} {:>12}", "waiting for M", self.stalls[Hazard::M as usize]);
+        println!("  {:<22} {:>12}   {} taken jumps", "Flushed by jumps", self.flushed, self.taken_jumps);
+
+        let mut sites: Vec<(&usize, &u64)> = self.stall_sites.iter().collect();
+        sites.sort_by_key(|(pc, stalls)| (Reverse(**stalls), **pc));
+        if !sites.is_empty() {
+            println!("Most stalled instructions");
+        }
+        for (pc, stalls) in sites.iter().take(Self::REPORT_ROWS) {
+            println!("  {:<34} {:>12}", debug_info.describe_pc(**pc), stalls);
+        }
+    }
+}
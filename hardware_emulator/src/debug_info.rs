@@ -0,0 +1,127 @@
+use std::{fs, collections::HashMap};
+
+use crate::{Result, Error};
+
+// Debug information produced by the assembler's --debug-info flag
+// Text format, one record per line:
+//   source <path>
+//   label <rom_address> <name>
+//   line <rom_address> <source_line>
+#[derive(Default)]
+pub struct DebugInfo {
+    pub source: Option<String>,             // Assembly file the program was compiled from
+    labels: Vec<(u16, String)>,             // Labels sorted by ROM address
+    functions: HashMap<u16, usize>,         // ROM address of VM functions to their label index
+    lines: Vec<usize>,                      // Source line of each instruction, indexed by ROM address
+}
+
+impl DebugInfo {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut source = None;
+        let mut labels = Vec::new();
+        let mut lines = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let invalid = |what: &str| Error::DebugInfoError(format!("Invalid {} at line {}", what, i + 1));
+
+            if let Some(path) = line.strip_prefix("source ") {
+                source = Some(path.to_string());
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                ["label", address, name] => {
+                    let address = address.parse::<u16>().map_err(|_| invalid("address"))?;
+                    labels.push((address, name.to_string()));
+                }
+                ["line", address, source_line] => {
+                    let address = address.parse::<usize>().map_err(|_| invalid("address"))?;
+                    let source_line = source_line.parse::<usize>().map_err(|_| invalid("source line"))?;
+                    if address != lines.len() {
+                        return Err(invalid("line record order"));
+                    }
+                    lines.push(source_line);
+                }
+                _ => return Err(invalid("record")),
+            }
+        }
+
+        // Stable sort keeps the source order of labels sharing an address
+        labels.sort_by_key(|(address, _)| *address);
+
+        let mut functions = HashMap::new();
+        for (i, (address, name)) in labels.iter().enumerate() {
+            if Self::is_function_name(name) {
+                functions.entry(*address).or_insert(i);
+            }
+        }
+
+        Ok(Self { source, labels, functions, lines })
+    }
+
+    // Number of instructions in the program, 0 if the line mapping is missing
+    pub fn program_size(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn source_line(&self, address: u16) -> Option<usize> {
+        self.lines.get(address as usize).copied()
+    }
+
+    // Labels of VM functions with their ROM address
+    pub fn functions(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.functions.iter().map(|(address, i)| (*address, self.labels[*i].1.as_str()))
+    }
+
+    // VM functions are named <Class>.<function>, labels inside them use a '$' separator
+    fn is_function_name(name: &str) -> bool {
+        name.contains('.') && !name.contains('$')
+    }
+
+    pub fn is_function_entry(&self, address: u16) -> bool {
+        self.functions.contains_key(&address)
+    }
+
+    // Label defined at the given address, VM function names are preferred
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        if let Some(i) = self.functions.get(&address) {
+            return Some(&self.labels[*i].1);
+        }
+        let i = self.labels.partition_point(|(a, _)| *a < address);
+        match self.labels.get(i) {
+            Some((a, name)) if *a == address => Some(name),
+            _ => None,
+        }
+    }
+
+    // Closest label at or before the given address, with the offset from it
+    pub fn locate(&self, address: u16) -> Option<(&str, u16)> {
+        let i = self.labels.partition_point(|(a, _)| *a <= address);
+        if i == 0 {
+            return None;
+        }
+        let label_address = self.labels[i - 1].0;
+        let name = self.label_at(label_address)?;
+        Some((name, address - label_address))
+    }
+
+    // Human readable ROM location, like `ball.move+12`
+    pub fn describe(&self, address: u16) -> String {
+        match self.locate(address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+{}", name, offset),
+            None => format!("{:04X}", address),
+        }
+    }
+
+    // Location of an executed instruction for diagnostics, like `PC 0063 (ball.move+12)`
+    pub fn describe_pc(&self, pc: usize) -> String {
+        match self.locate(pc as u16) {
+            Some(_) => format!("PC {:04X} ({})", pc, self.describe(pc as u16)),
+            None => format!("PC {:04X}", pc),
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use std::{io::{self, Read, Write}, sync::mpsc::{self, Receiver}, thread, time::Instant};
+
+use crate::{Result, Error, Memory, Keyboard, Audio, Disk};
+
+// Memory-mapped device, answering the reads and writes of a range of addresses
+//
+// Offsets are relative to the address the device is attached at. `cycle` is the number of
+// instructions executed before the one doing the access.
+pub trait Device {
+    fn name(&self) -> &'static str;
+    fn size(&self) -> usize;
+    fn read(&mut self, offset: usize, cycle: u64) -> u16;
+    fn write(&mut self, offset: usize, value: u16, cycle: u64);
+
+    // Called when the emulator stops after `cycle` instructions, to write the output files of the device
+    fn shutdown(&mut self, _cycle: u64) -> Result<()> {
+        Ok(())
+    }
+
+    // Words of a device which is plain memory, read and written without side effects by the
+    // display, the snapshots and the tools
+    fn memory(&self) -> Option<&[u16]> {
+        None
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [u16]> {
+        None
+    }
+}
+
+struct Mapping {
+    start: usize,
+    end: usize,     // Exclusive
+    device: Box<dyn Device>,
+}
+
+// Devices attached to the address space above the RAM: the screen memory map and the
+// keyboard register, then the extra devices in the free space above the keyboard register
+pub struct Bus {
+    mappings: Vec<Mapping>,
+    pub cycle: u64,     // Kept up to date by the execution engines, for the devices
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    pub const START: usize = Memory::RAM_SIZE;
+    pub const END: usize = 0x8000;      // Exclusive, the A register holding 15 bits
+    const BUILT_IN_DEVICES: usize = 2;
+
+    pub fn new() -> Self {
+        let built_in: [(usize, Box<dyn Device>); Self::BUILT_IN_DEVICES] = [
+            (Memory::SCREEN_MEMORY_MAP_OFFSET, Box::new(ScreenMemoryMap::new())),
+            (Memory::KEYBOARD_REG_OFFSET, Box::new(KeyboardRegister::default())),
+        ];
+        Self {
+            mappings: built_in.into_iter().map(|(start, device)| Mapping { start, end: start + device.size(), device }).collect(),
+            cycle: 0,
+        }
+    }
+
+    pub fn attach(&mut self, start: usize, device: Box<dyn Device>) -> Result<()> {
+        let end = start + device.size();
+        if start < Self::START || end > Self::END {
+            return Err(Error::DeviceError(format!("{} needs addresses {:04X}-{:04X}, outside of the bus {:04X}-{:04X}",
+                device.name(), start, end - 1, Self::START, Self::END - 1)));
+        }
+        if let Some(other) = self.mappings.iter().find(|mapping| start < mapping.end && mapping.start < end) {
+            return Err(Error::DeviceError(format!("{} at {:04X} overlaps {} at {:04X}", device.name(), start, other.device.name(), other.start)));
+        }
+        println!("Attached {} at {:04X}-{:04X}", device.name(), start, end - 1);
+        self.mappings.push(Mapping { start, end, device });
+        Ok(())
+    }
+
+    // Whether devices were attached besides the screen and the keyboard
+    pub fn has_extra_devices(&self) -> bool {
+        self.mappings.len() > Self::BUILT_IN_DEVICES
+    }
+
+    pub fn is_mapped(&self, address: usize) -> bool {
+        self.mappings.iter().any(|mapping| (mapping.start..mapping.end).contains(&address))
+    }
+
+    fn find(&mut self, address: usize) -> Result<(&mut dyn Device, usize)> {
+        match self.mappings.iter_mut().find(|mapping| (mapping.start..mapping.end).contains(&address)) {
+            Some(mapping) => Ok((mapping.device.as_mut(), address - mapping.start)),
+            None => Err(Error::InvalidMemoryAddress(address)),
+        }
+    }
+
+    // Backing memory of the device attached at `start`, if it is plain memory
+    pub fn memory(&self, start: usize) -> Option<&[u16]> {
+        self.mappings.iter().find(|mapping| mapping.start == start).and_then(|mapping| mapping.device.memory())
+    }
+
+    pub fn memory_mut(&mut self, start: usize) -> Option<&mut [u16]> {
+        self.mappings.iter_mut().find(|mapping| mapping.start == start).and_then(|mapping| mapping.device.memory_mut())
+    }
+
+    // Read without side effects, only from devices which are plain memory
+    pub fn peek(&self, address: usize) -> Result<u16> {
+        self.mappings.iter().find(|mapping| (mapping.start..mapping.end).contains(&address))
+            .and_then(|mapping| mapping.device.memory().map(|words| words[address - mapping.start]))
+            .ok_or(Error::InvalidMemoryAddress(address))
+    }
+
+    // Write without side effects, only to devices which are plain memory
+    pub fn poke(&mut self, address: usize, value: u16) -> Result<()> {
+        let word = self.mappings.iter_mut().find(|mapping| (mapping.start..mapping.end).contains(&address))
+            .and_then(|mapping| {
+                let offset = address - mapping.start;
+                mapping.device.memory_mut().map(|words| &mut words[offset])
+            })
+            .ok_or(Error::InvalidMemoryAddress(address))?;
+        *word = value;
+        Ok(())
+    }
+
+    pub fn read(&mut self, address: usize) -> Result<u16> {
+        let cycle = self.cycle;
+        let (device, offset) = self.find(address)?;
+        Ok(device.read(offset, cycle))
+    }
+
+    pub fn write(&mut self, address: usize, value: u16) -> Result<()> {
+        let cycle = self.cycle;
+        let (device, offset) = self.find(address)?;
+        device.write(offset, value, cycle);
+        Ok(())
+    }
+
+    pub fn shutdown(&mut self) -> Result<()> {
+        for mapping in self.mappings.iter_mut() {
+            mapping.device.shutdown(self.cycle)?;
+        }
+        Ok(())
+    }
+}
+
+// Screen memory map, 8K words of plain memory: each row of the 512x256 screen is 32 words,
+// the least significant bit of a word being its leftmost pixel
+pub struct ScreenMemoryMap {
+    words: Box<[u16; Memory::SCREEN_MEMORY_MAP_SIZE]>,
+}
+
+impl ScreenMemoryMap {
+    pub fn new() -> Self {
+        Self { words: Box::new([0; Memory::SCREEN_MEMORY_MAP_SIZE]) }
+    }
+}
+
+impl Default for ScreenMemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for ScreenMemoryMap {
+    fn name(&self) -> &'static str {
+        "screen"
+    }
+
+    fn size(&self) -> usize {
+        Memory::SCREEN_MEMORY_MAP_SIZE
+    }
+
+    fn read(&mut self, offset: usize, _cycle: u64) -> u16 {
+        self.words[offset]
+    }
+
+    fn write(&mut self, offset: usize, value: u16, _cycle: u64) {
+        self.words[offset] = value;
+    }
+
+    fn memory(&self) -> Option<&[u16]> {
+        Some(&self.words[..])
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [u16]> {
+        Some(&mut self.words[..])
+    }
+}
+
+// Keyboard register, one word set by the emulator to the code of the key held, 0 for none
+#[derive(Default)]
+pub struct KeyboardRegister {
+    word: [u16; 1],
+}
+
+impl Device for KeyboardRegister {
+    fn name(&self) -> &'static str {
+        "keyboard"
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize, _cycle: u64) -> u16 {
+        self.word[0]
+    }
+
+    // Writes are kept, as on the Hack computer, until the next key event
+    fn write(&mut self, _offset: usize, value: u16, _cycle: u64) {
+        self.word[0] = value;
+    }
+
+    fn memory(&self) -> Option<&[u16]> {
+        Some(&self.word)
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [u16]> {
+        Some(&mut self.word)
+    }
+}
+
+// Create a device from a NAME@ADDRESS[:ARGUMENT] specification, the address being decimal or
+// 0x hexadecimal, the argument a file used by the device. Devices measuring emulated time are
+// given the target clock rate.
+pub fn parse_device(spec: &str, cpu_cycles_per_second: u32) -> Result<(usize, Box<dyn Device>)> {
+    let error = || Error::DeviceError(format!("Invalid device '{}', expected NAME@ADDRESS[:ARGUMENT] like rng@0x6010", spec));
+    let (name, rest) = spec.split_once('@').ok_or_else(error)?;
+    let (address, argument) = match rest.split_once(':') {
+        Some((address, argument)) => (address, Some(argument)),
+        None => (rest, None),
+    };
+    let address = match address.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => address.parse(),
+    }.map_err(|_| error())?;
+
+    let device: Box<dyn Device> = match name {
+        "rng" => Box::new(Rng::new()),
+        "console" => Box::new(Console::new()),
+        "timer" => Box::new(Timer::new(cpu_cycles_per_second)),
+        "audio" => Box::new(Audio::new(argument, cpu_cycles_per_second)?),
+        "disk" => Box::new(Disk::new(argument)?),
+        _ => return Err(Error::DeviceError(format!("Unknown device '{}', expected rng, console, timer, audio or disk", name))),
+    };
+    Ok((address, device))
+}
+
+// Pseudo-random number generator, one register: reads return the next number,
+// writes set the seed, so that runs stay reproducible
+pub struct Rng {
+    state: u16,
+}
+
+impl Rng {
+    const DEFAULT_SEED: u16 = 0xACE1;
+
+    pub fn new() -> Self {
+        Self { state: Self::DEFAULT_SEED }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Rng {
+    fn name(&self) -> &'static str {
+        "rng"
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+
+    // 16-bit xorshift, never returning 0
+    fn read(&mut self, _offset: usize, _cycle: u64) -> u16 {
+        self.state ^= self.state << 7;
+        self.state ^= self.state >> 9;
+        self.state ^= self.state << 8;
+        self.state
+    }
+
+    fn write(&mut self, _offset: usize, value: u16, _cycle: u64) {
+        self.state = if value == 0 { Self::DEFAULT_SEED } else { value };
+    }
+}
+
+// Serial console, one register: writes print a character on the host's stdout, reads
+// return the next byte of the host's stdin, or NO_DATA when none is available yet
+//
+// The Hack newline (128) and the host newline are translated both ways. Stdin is read by
+// a background thread started on the first read, so that reads never block the CPU.
+pub struct Console {
+    input: Option<Receiver<u8>>,
+}
+
+impl Console {
+    pub const NO_DATA: u16 = 0xFFFF;
+
+    pub fn new() -> Self {
+        Self { input: None }
+    }
+
+    fn spawn_reader() -> Receiver<u8> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        receiver
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Console {
+    fn name(&self) -> &'static str {
+        "console"
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize, _cycle: u64) -> u16 {
+        match self.input.get_or_insert_with(Self::spawn_reader).try_recv() {
+            Ok(b'\n') => Keyboard::NEWLINE,
+            Ok(byte) => byte as u16,
+            Err(_) => Self::NO_DATA,
+        }
+    }
+
+    fn write(&mut self, _offset: usize, value: u16, _cycle: u64) {
+        let byte = match value {
+            Keyboard::NEWLINE => b'\n',
+            0..=0x7F => value as u8,
+            _ => return,
+        };
+        let mut stdout = io::stdout().lock();
+        // Losing console output must not stop the emulated program
+        let _ = stdout.write_all(&[byte]).and_then(|_| stdout.flush());
+    }
+}
+
+// Timer, five registers:
+//   0  cycle counter, low 16 bits, reading it latches the high bits
+//   1  cycle counter, high 16 bits as of the last read of register 0
+//   2  wall-clock milliseconds since start, low 16 bits, reading it latches the high bits
+//   3  wall-clock milliseconds, high 16 bits as of the last read of register 2
+//   4  countdown: writing N starts a wait of N emulated milliseconds, reads return the
+//      milliseconds left, 0 once elapsed
+//
+// Emulated milliseconds are counted in cycles at the target clock rate, so that a program
+// waiting on the countdown runs at the same speed whatever the clock rate, and the same
+// way headless.
+pub struct Timer {
+    cycles_per_millisecond: u64,
+    start: Instant,
+    latched_cycles: u16,
+    latched_milliseconds: u16,
+    deadline: u64,      // Cycle at which the countdown reaches 0
+}
+
+impl Timer {
+    pub fn new(cpu_cycles_per_second: u32) -> Self {
+        Self {
+            cycles_per_millisecond: (cpu_cycles_per_second as u64 / 1000).max(1),
+            start: Instant::now(),
+            latched_cycles: 0,
+            latched_milliseconds: 0,
+            deadline: 0,
+        }
+    }
+}
+
+impl Device for Timer {
+    fn name(&self) -> &'static str {
+        "timer"
+    }
+
+    fn size(&self) -> usize {
+        5
+    }
+
+    fn read(&mut self, offset: usize, cycle: u64) -> u16 {
+        match offset {
+            0 => {
+                self.latched_cycles = (cycle >> 16) as u16;
+                cycle as u16
+            }
+            1 => self.latched_cycles,
+            2 => {
+                let milliseconds = self.start.elapsed().as_millis() as u64;
+                self.latched_milliseconds = (milliseconds >> 16) as u16;
+                milliseconds as u16
+            }
+            3 => self.latched_milliseconds,
+            _ => {
+                let remaining = self.deadline.saturating_sub(cycle);
+                remaining.div_ceil(self.cycles_per_millisecond).min(u16::MAX as u64) as u16
+            }
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u16, cycle: u64) {
+        if offset == 4 {
+            self.deadline = cycle + value as u64 * self.cycles_per_millisecond;
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{Result, Error, Device, DiskImage};
+
+// Block storage backed by a disk image, 260 registers:
+//   0      sector number
+//   1      command: writing READ copies the sector to the buffer, WRITE copies the buffer to the sector
+//   2      status of the last command, read-only
+//   3      number of sectors of the image, read-only
+//   4-259  sector buffer, 256 words
+//
+// Commands complete immediately, writes reaching the image file before the next instruction.
+pub struct Disk {
+    image: DiskImage,
+    sector: u16,
+    command: u16,
+    status: u16,
+    buffer: [u16; DiskImage::SECTOR_WORDS],
+}
+
+impl Disk {
+    pub const READ: u16 = 1;
+    pub const WRITE: u16 = 2;
+
+    pub const STATUS_OK: u16 = 0;
+    pub const STATUS_INVALID_SECTOR: u16 = 1;
+    pub const STATUS_INVALID_COMMAND: u16 = 2;
+    pub const STATUS_IO_ERROR: u16 = 3;
+
+    const BUFFER_OFFSET: usize = 4;
+
+    pub fn new(path: Option<&str>) -> Result<Self> {
+        let path = path.ok_or_else(|| Error::DeviceError("The disk device needs an image, like disk@0x6100:disk.img".to_string()))?;
+        let image = DiskImage::open(path).map_err(|e| Error::DeviceError(format!("Disk image {}: {}", path, e)))?;
+        Ok(Self {
+            image,
+            sector: 0,
+            command: 0,
+            status: Self::STATUS_OK,
+            buffer: [0; DiskImage::SECTOR_WORDS],
+        })
+    }
+
+    fn run_command(&mut self) -> u16 {
+        if self.sector as u64 >= self.image.sectors() {
+            return Self::STATUS_INVALID_SECTOR;
+        }
+        let result = match self.command {
+            Self::READ => self.image.read_sector(self.sector as u64, &mut self.buffer),
+            Self::WRITE => self.image.write_sector(self.sector as u64, &self.buffer),
+            _ => return Self::STATUS_INVALID_COMMAND,
+        };
+        match result {
+            Ok(()) => Self::STATUS_OK,
+            Err(_) => Self::STATUS_IO_ERROR,
+        }
+    }
+}
+
+impl Device for Disk {
+    fn name(&self) -> &'static str {
+        "disk"
+    }
+
+    fn size(&self) -> usize {
+        Self::BUFFER_OFFSET + DiskImage::SECTOR_WORDS
+    }
+
+    fn read(&mut self, offset: usize, _cycle: u64) -> u16 {
+        match offset {
+            0 => self.sector,
+            1 => self.command,
+            2 => self.status,
+            3 => self.image.sectors().min(u16::MAX as u64) as u16,
+            _ => self.buffer[offset - Self::BUFFER_OFFSET],
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u16, _cycle: u64) {
+        match offset {
+            0 => self.sector = value,
+            1 => {
+                self.command = value;
+                self.status = self.run_command();
+            }
+            2 | 3 => {}
+            _ => self.buffer[offset - Self::BUFFER_OFFSET] = value,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::{fs::{File, OpenOptions}, io::{self, Read, Seek, SeekFrom, Write}};
+
+// Disk image: a host file holding sectors of 256 words, stored big-endian, without header
+//
+// Shared by the disk device of the emulator and the hack_disk tool.
+pub struct DiskImage {
+    file: File,
+    sectors: u64,
+}
+
+impl DiskImage {
+    pub const SECTOR_WORDS: usize = 256;
+    pub const SECTOR_BYTES: u64 = Self::SECTOR_WORDS as u64 * 2;
+
+    // Create an image of blank sectors, failing if the file exists
+    pub fn create(path: &str, sectors: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(path)?;
+        file.set_len(sectors * Self::SECTOR_BYTES)?;
+        Ok(Self { file, sectors })
+    }
+
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let size = file.metadata()?.len();
+        if size % Self::SECTOR_BYTES != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("{} is not a disk image, its size is not a multiple of {} bytes", path, Self::SECTOR_BYTES)));
+        }
+        Ok(Self { file, sectors: size / Self::SECTOR_BYTES })
+    }
+
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    pub fn read_sector(&mut self, sector: u64, words: &mut [u16; Self::SECTOR_WORDS]) -> io::Result<()> {
+        let mut bytes = [0; Self::SECTOR_BYTES as usize];
+        self.seek(sector)?;
+        self.file.read_exact(&mut bytes)?;
+        for (word, pair) in words.iter_mut().zip(bytes.chunks_exact(2)) {
+            *word = u16::from_be_bytes([pair[0], pair[1]]);
+        }
+        Ok(())
+    }
+
+    pub fn write_sector(&mut self, sector: u64, words: &[u16; Self::SECTOR_WORDS]) -> io::Result<()> {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        self.seek(sector)?;
+        self.file.write_all(&bytes)
+    }
+
+    fn seek(&mut self, sector: u64) -> io::Result<()> {
+        if sector >= self.sectors {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Sector {} is beyond the {} sectors of the image", sector, self.sectors)));
+        }
+        self.file.seek(SeekFrom::Start(sector * Self::SECTOR_BYTES))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use std::{fs, str::FromStr};
+
+use crate::{Result, Error};
+
+// Colors of the set (1) and clear (0) pixels of the screen memory map
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub on: [u8; 3],
+    pub off: [u8; 3],
+}
+
+impl Palette {
+    pub const OFFICIAL: Palette = Palette { on: [0x00, 0x00, 0x00], off: [0xFF, 0xFF, 0xFF] };
+    pub const INVERTED: Palette = Palette { on: [0xFF, 0xFF, 0xFF], off: [0x00, 0x00, 0x00] };
+    pub const GREEN: Palette = Palette { on: [0x33, 0xFF, 0x66], off: [0x0A, 0x14, 0x0A] };
+    pub const AMBER: Palette = Palette { on: [0xFF, 0xB0, 0x00], off: [0x14, 0x0C, 0x00] };
+
+    fn parse_color(text: &str) -> Option<[u8; 3]> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        if hex.len() != 6 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    // A preset name, or custom colors as ON:OFF in RRGGBB hexadecimal
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        match text {
+            "official" => Ok(Self::OFFICIAL),
+            "inverted" => Ok(Self::INVERTED),
+            "green" => Ok(Self::GREEN),
+            "amber" => Ok(Self::AMBER),
+            _ => {
+                let colors = text.split_once(':').and_then(|(on, off)| Some((Self::parse_color(on)?, Self::parse_color(off)?)));
+                match colors {
+                    Some((on, off)) => Ok(Self { on, off }),
+                    None => Err(format!("Invalid palette '{}', expected official, inverted, green, amber or ON:OFF colors like 000000:FFFFFF", text)),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    Integer,    // Largest whole multiple of the Hack resolution fitting the window
+    Fit,        // Fill the window as much as possible, keeping the aspect ratio
+}
+
+impl FromStr for Scaling {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        match text {
+            "integer" => Ok(Self::Integer),
+            "fit" => Ok(Self::Fit),
+            _ => Err(format!("Invalid scaling '{}', expected integer or fit", text)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Smooth,     // Bilinear interpolation between neighbouring Hack pixels
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        match text {
+            "nearest" => Ok(Self::Nearest),
+            "smooth" => Ok(Self::Smooth),
+            _ => Err(format!("Invalid filter '{}', expected nearest or smooth", text)),
+        }
+    }
+}
+
+// How the screen memory map is shown in the window
+#[derive(Clone, Copy)]
+pub struct DisplayOptions {
+    pub palette: Palette,
+    pub scaling: Scaling,
+    pub filter: Filter,
+    pub grid: bool,         // Darken the borders between Hack pixels
+    pub fullscreen: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            palette: Palette::OFFICIAL,
+            scaling: Scaling::Integer,
+            filter: Filter::Nearest,
+            grid: false,
+            fullscreen: false,
+        }
+    }
+}
+
+impl DisplayOptions {
+    // Read `key = value` lines, '#' starting a comment, over the default options
+    pub fn from_file(path: &str) -> Result<Self> {
+        let mut options = Self::default();
+        for (index, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: String| Error::ConfigError(format!("{}:{}: {}", path, index + 1, message));
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected key = value".to_string()))?;
+            let value = value.trim();
+            match key.trim() {
+                "palette" => options.palette = value.parse().map_err(error)?,
+                "scaling" => options.scaling = value.parse().map_err(error)?,
+                "filter" => options.filter = value.parse().map_err(error)?,
+                "grid" => options.grid = Self::parse_bool(value).map_err(error)?,
+                "fullscreen" => options.fullscreen = Self::parse_bool(value).map_err(error)?,
+                key => return Err(error(format!("unknown option '{}'", key))),
+            }
+        }
+        Ok(options)
+    }
+
+    fn parse_bool(value: &str) -> std::result::Result<bool, String> {
+        match value {
+            "true" | "yes" | "on" => Ok(true),
+            "false" | "no" | "off" => Ok(false),
+            _ => Err(format!("Invalid boolean '{}', expected true or false", value)),
+        }
+    }
+}
@@ -1,41 +1,159 @@
+use std::time::{Duration, Instant};
+
 use game_loop::{game_loop, GameLoop, Time};
-use pixels::Pixels;
-use winit::{event_loop::EventLoop, window::Window, event::{ Event,  DeviceEvent, ElementState, WindowEvent}};
+use winit::{event_loop::EventLoop, window::{Window, Fullscreen}, event::{Event, WindowEvent, ModifiersState, VirtualKeyCode}};
 
-use crate::{error::Result, keyboard::Keyboard, screen::Screen, Cpu, Rom, Memory, CpuOutput};
+use crate::{error::Result, keyboard::{Keyboard, KeyEvent}, screen::Screen, Cpu, Rom, Memory, CpuOutput, Snapshot, DebugInfo, Profiler, Coverage, FastEngine, Clock, SpeedMode, Palette, Recorder, save_screenshot, ScreenAssertion, InputScript, InputRecorder, Protection, UninitializedReads, Statistics, CpuModel, MachineInit, ResetMode};
 
 pub struct Emulator {
     pub rom: Rom,
     pub memory: Memory,
     pub cpu: Cpu,
-    pub screen: Screen,
+    pub screen: Option<Screen>,     // None when running headless
     pub keyboard: Keyboard,
+    pub init: MachineInit,          // Registers and RAM words set at start and on reset
+    pub state_path: String,         // File used by the save/load state hotkeys
+    pub debug_info: DebugInfo,
+    pub profiler: Option<Profiler>,
+    pub coverage: Option<Coverage>,
+    pub cycles: u64,                // Instructions executed since start
+    pub max_cycles: Option<u64>,    // Stop after this many instructions
+    pub engine: Option<FastEngine>, // Pre-decoded execution engine, the interpreter is used when None
+    pub clock: Clock,
+    pub palette: Palette,                   // Colors of screenshots and recordings
+    pub recorder: Option<Recorder>,
+    pub record_fps: u32,                    // Emulated frame rate of recordings
+    pub screenshot_path: Option<String>,    // Screenshot written when the emulator stops
+    pub capture_prefix: String,             // Start of the names of the files written by the capture hotkeys
+    pub screen_assertion: Option<ScreenAssertion>,  // Reference image the screen must match when the emulator stops
+    pub input_script: Option<InputScript>,          // Keyboard input replayed at fixed cycles
+    pub input_recorder: Option<InputRecorder>,      // Records the live keyboard input
+    pub protection: Option<Protection>,             // Trap policies checked on every instruction
+    pub uninitialized_reads: Option<UninitializedReads>,    // Warns about reads of never written memory and registers
+    pub statistics: Option<Statistics>,             // Instruction mix and memory traffic, reported when the emulator stops
+    pub cpu_model: Option<Box<dyn CpuModel>>,       // Timing model selected at startup, None runs the reference CPU alone
+    modifiers: ModifiersState,
 } 
 
 impl Emulator {
-    pub fn new(rom: Rom, pixels: Pixels) -> Self {
+    const BATCH_SIZE: u64 = 1 << 16;   // Instructions run between checks of the stop conditions
+    const DEFAULT_CLOCK_HZ: u32 = 2_000_000;
+    const FRAME_BUDGET: f64 = 0.75;    // Share of a frame the CPU may use, the rest is left to rendering
+    pub const DEFAULT_RECORD_FPS: u32 = 25;
+    const WINDOW_TITLE: &'static str = "Hack Computer Emulator";
+
+    pub fn new(rom: Rom, screen: Option<Screen>, state_path: String) -> Self {
         Emulator {
             rom,
             memory: Memory::new(),
             cpu: Cpu::new(),
-            screen: Screen::new(pixels),
+            screen,
             keyboard: Keyboard::default(),
+            init: MachineInit::default(),
+            state_path,
+            debug_info: DebugInfo::default(),
+            profiler: None,
+            coverage: None,
+            cycles: 0,
+            max_cycles: None,
+            engine: None,
+            clock: Clock::new(Self::DEFAULT_CLOCK_HZ),
+            palette: Palette::OFFICIAL,
+            recorder: None,
+            record_fps: Self::DEFAULT_RECORD_FPS,
+            screenshot_path: None,
+            capture_prefix: "capture".to_string(),
+            screen_assertion: None,
+            input_script: None,
+            input_recorder: None,
+            protection: None,
+            uninitialized_reads: None,
+            statistics: None,
+            cpu_model: None,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    // Use the fast engine, unless a tool needs to observe every instruction
+    pub fn enable_fast_engine(&mut self) {
+        if self.profiler.is_some() || self.coverage.is_some() || self.protection.is_some() || self.uninitialized_reads.is_some()
+            || self.statistics.is_some() || self.cpu_model.as_ref().is_some_and(|model| model.needs_interpreter()) {
+            println!("Fast engine disabled: profiling, coverage, traps, shadow memory, statistics and the pipeline model require the interpreter");
+            return;
+        }
+        self.engine = Some(FastEngine::new(&self.rom));
+    }
+
+    // Initialize the machine, before a state is loaded so that it can override it
+    pub fn set_init(&mut self, init: MachineInit) -> Result<()> {
+        init.apply(&mut self.cpu, &mut self.memory)?;
+        self.init = init;
+        Ok(())
+    }
+
+    // Send the PC back to the start address, clearing the memory as configured
+    pub fn reset(&mut self) -> Result<()> {
+        if self.init.reset == ResetMode::Pc {
+            self.cpu.pc = self.init.start_pc();
+        } else {
+            self.memory.words.fill(0);
+            if self.init.reset == ResetMode::All {
+                self.memory.screen_mut().fill(0);
+            }
+            self.init.apply(&mut self.cpu, &mut self.memory)?;
+            if let Some(protection) = &mut self.protection {
+                protection.reset(&self.init);
+            }
+            if let Some(reads) = &mut self.uninitialized_reads {
+                reads.reset(&self.init);
+            }
         }
+        println!("Reset at cycle {}", self.cycles);
+        Ok(())
     }
 
-    pub fn run(mut self, event_loop: EventLoop<()>, window: Window, cpu_cycle_per_second: u32) -> Result<()> {
+    pub fn save_state(&self, path: &str) -> Result<()> {
+        Snapshot::capture(&self.cpu, &self.memory).save(path)?;
+        println!("State saved to {}", path);
+        Ok(())
+    }
+
+    pub fn load_state(&mut self, path: &str) -> Result<()> {
+        Snapshot::load(path)?.restore(&mut self.cpu, &mut self.memory);
+        if let Some(protection) = &mut self.protection {
+            protection.mark_all_written();
+        }
+        if let Some(reads) = &mut self.uninitialized_reads {
+            reads.mark_all_written();
+        }
+        println!("State loaded from {}", path);
+        Ok(())
+    }
+
+    // Run in a window, one batch of instructions per frame, at the clock rate
+    pub fn run(self, event_loop: EventLoop<()>, window: Window) -> Result<()> {
         println!("Running emulator");
 
-        self.memory.store(0, 2000)?;
-        game_loop(event_loop, window, self, cpu_cycle_per_second, 0.1, |g| {
-            if let Err(e) = g.game.tick(){
+        game_loop(event_loop, window, self, Clock::FRAMES_PER_SECOND, 0.1, |g| {
+            let first_cycle = g.game.cycles;
+            let result = g.game.run_frame(g.fixed_time_step());
+            if let Err(e) = result {
                 println!("{}", e);
                 g.exit();
+            } else if g.game.finished() {
+                g.exit();
+            }
+            if g.game.clock.record(g.game.cycles - first_cycle) {
+                g.window.set_title(&format!("{} - {}", Self::WINDOW_TITLE, g.game.clock.status()));
             }
         }, |g| {
-            if let Err(e) = g.game.screen.render(){
-                println!("{}", e);
-                g.exit();
+            let game = &mut g.game;
+            if let Some(screen) = &mut game.screen {
+                screen.update(&game.memory);
+                if let Err(e) = screen.render(){
+                    println!("{}", e);
+                    g.exit();
+                }
             }
         },  |g, event| {
             if let Err(e) = Self::handle_event(g, event) {
@@ -45,41 +163,240 @@ impl Emulator {
         });
     }
 
-    fn handle_event(g: &mut GameLoop<Emulator, Time, Window>, event: &Event<()>) -> Result<()>{
-        match event {
-            Event::DeviceEvent {event, .. } => {
-                if let DeviceEvent::Key(keyboard_input) = event {
-                    if keyboard_input.state == ElementState::Released {
-                        g.game.memory.set_keyboard_reg(0);
-                    }
-                    else {
-                        let keycode= g.game.keyboard.keycode_from_winit(keyboard_input.scancode);
-                        g.game.memory.set_keyboard_reg(keycode);
-                    }
+    // Run without a window as fast as possible, until max_cycles is reached or an error occurs
+    pub fn run_headless(mut self) -> Result<()> {
+        println!("Running emulator headless");
+
+        let start = Instant::now();
+        let result = self.run_to_end();
+        let elapsed = start.elapsed().as_secs_f64();
+        println!("Executed {} instructions in {:.3}s ({:.2} MHz)", self.cycles, elapsed, self.cycles as f64 / elapsed / 1e6);
+
+        self.shutdown()?;
+        result
+    }
+
+    // Execute instructions until max_cycles is reached
+    pub fn run_to_end(&mut self) -> Result<()> {
+        while !self.finished() {
+            self.step(Self::BATCH_SIZE)?;
+        }
+        Ok(())
+    }
+
+    // Execute the instructions of one frame, as many as the clock allows
+    // If the host cannot keep up, or in turbo mode, the batch stops once it used its share of
+    // the frame, so that the game loop never falls behind and catches up with extra updates
+    fn run_frame(&mut self, frame_time: f64) -> Result<()> {
+        let start = Instant::now();
+        let deadline = Duration::from_secs_f64(frame_time * Self::FRAME_BUDGET);
+        let last_cycle = self.clock.cycles_for_frame(frame_time).map(|cycles| self.cycles + cycles);
+
+        while last_cycle.is_none_or(|last| self.cycles < last) && start.elapsed() < deadline && !self.finished() {
+            let batch = last_cycle.map_or(Self::BATCH_SIZE, |last| (last - self.cycles).min(Self::BATCH_SIZE));
+            self.step(batch)?;
+        }
+        Ok(())
+    }
+
+    // Execute up to `count` instructions, without going past max_cycles, the next recorded frame
+    // nor the next scripted input
+    pub fn step(&mut self, count: u64) -> Result<()> {
+        let mut limit = self.cycles + count;
+        if let Some(max) = self.max_cycles {
+            limit = limit.min(max);
+        }
+        if let Some(recorder) = &self.recorder {
+            limit = limit.min(recorder.next_frame());
+        }
+        if let Some(script) = &mut self.input_script {
+            script.apply(&mut self.memory, self.cycles);
+            if let Some(cycle) = script.next_cycle() {
+                limit = limit.min(cycle);
+            }
+        }
+
+        match &self.engine {
+            Some(engine) => engine.run(&mut self.cpu, &mut self.memory, &mut self.cycles, limit)?,
+            None => {
+                while self.cycles < limit {
+                    self.tick()?;
                 }
             }
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.capture(&self.memory, self.cycles)?;
+        }
+        Ok(())
+    }
+
+    pub fn start_recording(&mut self, path: String) -> Result<()> {
+        self.recorder = Some(Recorder::new(path, self.palette, self.clock.target_hz, self.record_fps, self.cycles)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.max_cycles.is_some_and(|max| self.cycles >= max)
+    }
+
+    // Write the reports of the enabled tools, the final screenshot and the recording, then check the screen
+    fn shutdown(&mut self) -> Result<()> {
+        self.stop_recording()?;
+        self.memory.bus.cycle = self.cycles;
+        self.memory.bus.shutdown()?;
+        if let Some(recorder) = &self.input_recorder {
+            recorder.save()?;
+        }
+        if let Some(path) = &self.screenshot_path {
+            save_screenshot(path, &self.memory, self.palette)?;
+        }
+        if let Some(profiler) = &self.profiler {
+            profiler.write_reports(&self.debug_info)?;
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.write_lcov(&self.rom, &self.debug_info)?;
+        }
+        if let Some(reads) = &self.uninitialized_reads {
+            reads.report();
+        }
+        if let Some(statistics) = &self.statistics {
+            statistics.report()?;
+        }
+        if let Some(model) = &self.cpu_model {
+            model.report(self.cycles, &self.debug_info);
+        }
+        if let Some(assertion) = &self.screen_assertion {
+            assertion.check(&self.memory)?;
+        }
+        Ok(())
+    }
+
+    fn handle_event(g: &mut GameLoop<Emulator, Time, Window>, event: &Event<()>) -> Result<()>{
+        match event {
+            Event::LoopDestroyed => g.game.shutdown()?,
             Event::WindowEvent { window_id: _, event} => match event {
                 WindowEvent::CloseRequested => g.exit(),
                 WindowEvent::Resized(size) => {
-                    g.game.screen.pixels.resize_surface(size.width, size.height);
+                    if let Some(screen) = &mut g.game.screen {
+                        screen.resize(size.width, size.height);
+                    }
+                }
+                WindowEvent::ModifiersChanged(modifiers) => {
+                    g.game.modifiers = *modifiers;
+                }
+                event => if let Some(key_event) = KeyEvent::from_window_event(event) {
+                    match g.game.keyboard.handle_event(key_event, g.game.modifiers) {
+                        Some(VirtualKeyCode::F) => {
+                            let fullscreen = match g.window.fullscreen() {
+                                Some(_) => None,
+                                None => Some(Fullscreen::Borderless(None)),
+                            };
+                            g.window.set_fullscreen(fullscreen);
+                        }
+                        // A failed hotkey, like loading a state never saved, leaves the session running
+                        Some(key) => if let Err(e) = g.game.handle_hotkey(key) {
+                            println!("Hotkey error: {}", e);
+                        },
+                        None => g.game.update_keyboard_reg(),
+                    }
                 }
-                _ => {}
             },
             _ => {}
         }
         Ok(())
     }
 
+    // Copy the live keyboard state to the keyboard register
+    fn update_keyboard_reg(&mut self) {
+        self.memory.set_keyboard_reg(self.keyboard.reg);
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.record(self.cycles, self.keyboard.reg);
+        }
+    }
+
+    fn handle_hotkey(&mut self, key: VirtualKeyCode) -> Result<()> {
+        match key {
+            VirtualKeyCode::P => {
+                self.clock.toggle(SpeedMode::Paused);
+                Ok(())
+            }
+            VirtualKeyCode::T => {
+                self.clock.toggle(SpeedMode::Turbo);
+                Ok(())
+            }
+            VirtualKeyCode::M => {
+                self.clock.toggle(SpeedMode::SlowMotion);
+                Ok(())
+            }
+            VirtualKeyCode::G => {
+                if let Some(screen) = &mut self.screen {
+                    let mut options = screen.options();
+                    options.grid = !options.grid;
+                    screen.set_options(options);
+                }
+                Ok(())
+            }
+            VirtualKeyCode::C => {
+                let path = format!("{}.{}.png", self.capture_prefix, self.cycles);
+                save_screenshot(&path, &self.memory, self.palette)
+            }
+            VirtualKeyCode::V => {
+                if self.recorder.is_some() {
+                    self.stop_recording()
+                } else {
+                    self.start_recording(format!("{}.{}.gif", self.capture_prefix, self.cycles))
+                }
+            }
+            VirtualKeyCode::R => self.reset(),
+            VirtualKeyCode::S => self.save_state(&self.state_path),
+            VirtualKeyCode::L => {
+                let path = self.state_path.clone();
+                self.load_state(&path)
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn tick(&mut self) -> Result<()> {
-        let instruction = self.rom.fetch(self.cpu.pc)?;
+        let pc = self.cpu.pc;
+        let instruction = self.rom.fetch(pc)?;
         
-        let cpu_output: CpuOutput = self.cpu.execute(instruction, &self.memory)?;
+        self.memory.bus.cycle = self.cycles;
+        if let Some(protection) = &self.protection {
+            protection.check_access(pc, instruction, &self.cpu, &self.memory, &self.debug_info)?;
+        }
+        let cpu_output: CpuOutput = match &mut self.cpu_model {
+            Some(model) => model.execute(&mut self.cpu, instruction, &mut self.memory)?,
+            None => self.cpu.execute(instruction, &mut self.memory)?,
+        };
+        if let Some(protection) = &mut self.protection {
+            protection.check(pc, instruction, &self.cpu, &cpu_output, &self.debug_info)?;
+        }
+        if let Some(reads) = &mut self.uninitialized_reads {
+            reads.record(pc, instruction, &cpu_output, &self.debug_info);
+        }
+        if let Some(statistics) = &mut self.statistics {
+            statistics.record(instruction, &cpu_output);
+        }
 
         if cpu_output.write_to_ram {
             self.memory.store(cpu_output.memory_address, cpu_output.alu_out)?;
-            if Memory::is_screen_address(cpu_output.memory_address) {
-                self.screen.write(cpu_output.memory_address, cpu_output.alu_out)?;
-            }
+        }
+
+        self.cycles += 1;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(pc, self.cpu.pc, &self.memory, &self.debug_info);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc, instruction, &cpu_output);
         }
 
         Ok(())
@@ -0,0 +1,211 @@
+use crate::{Result, Error, Cpu, Memory, Rom};
+
+// ALU computations, the y operand being either A or M
+#[derive(Clone, Copy)]
+enum Alu {
+    Zero,
+    One,
+    MinusOne,
+    D,
+    Y,
+    NotD,
+    NotY,
+    NegD,
+    NegY,
+    DPlusOne,
+    YPlusOne,
+    DMinusOne,
+    YMinusOne,
+    DPlusY,
+    DMinusY,
+    YMinusD,
+    DAndY,
+    DOrY,
+}
+
+// C instruction with its fields decoded once
+#[derive(Clone, Copy)]
+struct Compute {
+    alu: Alu,
+    use_m: bool,    // y operand is M instead of A
+    dest: u8,       // Same bits as the instruction: A, D, M
+    jump: u8,       // Same bits as the instruction: lt, eq, gt
+}
+
+#[derive(Clone, Copy)]
+enum MicroOp {
+    LoadA(u16),
+    Compute(Compute),
+    LoadACompute(u16, Compute),     // Superinstruction: an A instruction followed by a C instruction
+    Fallback(u16),                  // Invalid instruction, executed by the reference Cpu to report the same error
+}
+
+// Execution engine running a pre-decoded copy of the ROM
+//
+// Every ROM address holds one micro-op, so that jumps can land anywhere. An A instruction
+// directly followed by a valid C instruction is fused into a single micro-op, the C
+// instruction keeping its own micro-op at the next address.
+pub struct FastEngine {
+    ops: Box<[MicroOp]>,
+}
+
+impl FastEngine {
+    pub fn new(rom: &Rom) -> Self {
+        let words = rom.words();
+        let decoded: Vec<Option<Compute>> = words.iter().map(|word| Self::decode_c_instruction(*word)).collect();
+
+        let ops = words.iter().enumerate().map(|(address, word)| {
+            if word >> 15 == 0 {
+                match decoded.get(address + 1) {
+                    Some(Some(compute)) => MicroOp::LoadACompute(*word, *compute),
+                    _ => MicroOp::LoadA(*word),
+                }
+            } else {
+                match decoded[address] {
+                    Some(compute) => MicroOp::Compute(compute),
+                    None => MicroOp::Fallback(*word),
+                }
+            }
+        }).collect();
+
+        Self { ops }
+    }
+
+    fn decode_c_instruction(instruction: u16) -> Option<Compute> {
+        if instruction >> 13 != 0b111 {
+            return None;
+        }
+
+        let alu = match (instruction >> 6) & 0b111111 {
+            0b101010 => Alu::Zero,
+            0b111111 => Alu::One,
+            0b111010 => Alu::MinusOne,
+            0b001100 => Alu::D,
+            0b110000 => Alu::Y,
+            0b001101 => Alu::NotD,
+            0b110001 => Alu::NotY,
+            0b001111 => Alu::NegD,
+            0b110011 => Alu::NegY,
+            0b011111 => Alu::DPlusOne,
+            0b110111 => Alu::YPlusOne,
+            0b001110 => Alu::DMinusOne,
+            0b110010 => Alu::YMinusOne,
+            0b000010 => Alu::DPlusY,
+            0b010011 => Alu::DMinusY,
+            0b000111 => Alu::YMinusD,
+            0b000000 => Alu::DAndY,
+            0b010101 => Alu::DOrY,
+            _ => return None,
+        };
+
+        Some(Compute {
+            alu,
+            use_m: (instruction >> 12) & 0b1 != 0,
+            dest: ((instruction >> 3) & 0b111) as u8,
+            jump: (instruction & 0b111) as u8,
+        })
+    }
+
+    // Execute instructions until `cycles` reaches `limit`, with the same results as Cpu::execute
+    pub fn run(&self, cpu: &mut Cpu, memory: &mut Memory, cycles: &mut u64, limit: u64) -> Result<()> {
+        while *cycles < limit {
+            let op = match self.ops.get(cpu.pc) {
+                Some(op) => *op,
+                None => return Err(Error::InvalidROMAddress(cpu.pc)),
+            };
+
+            match op {
+                MicroOp::LoadA(value) => {
+                    cpu.reg_a = value;
+                    cpu.pc += 1;
+                    *cycles += 1;
+                }
+                MicroOp::Compute(compute) => {
+                    Self::compute(compute, cpu, memory, *cycles)?;
+                    *cycles += 1;
+                }
+                MicroOp::LoadACompute(value, compute) => {
+                    cpu.reg_a = value;
+                    cpu.pc += 1;
+                    *cycles += 1;
+                    if *cycles < limit {
+                        Self::compute(compute, cpu, memory, *cycles)?;
+                        *cycles += 1;
+                    }
+                }
+                MicroOp::Fallback(instruction) => {
+                    memory.bus.cycle = *cycles;
+                    let cpu_output = cpu.execute(instruction, memory)?;
+                    if cpu_output.write_to_ram {
+                        memory.store(cpu_output.memory_address, cpu_output.alu_out)?;
+                    }
+                    *cycles += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn compute(compute: Compute, cpu: &mut Cpu, memory: &mut Memory, cycle: u64) -> Result<()> {
+        let d = cpu.reg_d;
+        let y = if compute.use_m {
+            match memory.words.get(cpu.reg_a as usize) {
+                Some(value) => *value,
+                None => {
+                    memory.bus.cycle = cycle;
+                    memory.fetch(cpu.reg_a as usize)?
+                }
+            }
+        } else {
+            cpu.reg_a
+        };
+
+        let out = match compute.alu {
+            Alu::Zero => 0,
+            Alu::One => 1,
+            Alu::MinusOne => 0xFFFF,
+            Alu::D => d,
+            Alu::Y => y,
+            Alu::NotD => !d,
+            Alu::NotY => !y,
+            Alu::NegD => d.wrapping_neg(),
+            Alu::NegY => y.wrapping_neg(),
+            Alu::DPlusOne => d.wrapping_add(1),
+            Alu::YPlusOne => y.wrapping_add(1),
+            Alu::DMinusOne => d.wrapping_sub(1),
+            Alu::YMinusOne => y.wrapping_sub(1),
+            Alu::DPlusY => d.wrapping_add(y),
+            Alu::DMinusY => d.wrapping_sub(y),
+            Alu::YMinusD => y.wrapping_sub(d),
+            Alu::DAndY => d & y,
+            Alu::DOrY => d | y,
+        };
+
+        let memory_address = cpu.reg_a as usize;
+        if compute.dest & 0b010 != 0 {
+            cpu.reg_d = out;
+        }
+        if compute.dest & 0b100 != 0 {
+            cpu.reg_a = out;
+        }
+
+        let condition = if out == 0 { 0b010 } else if out & 0x8000 != 0 { 0b100 } else { 0b001 };
+        if compute.jump & condition != 0 {
+            cpu.pc = cpu.reg_a as usize;
+        } else {
+            cpu.pc += 1;
+        }
+
+        if compute.dest & 0b001 != 0 {
+            match memory.words.get_mut(memory_address) {
+                Some(word) => *word = out,
+                None => {
+                    memory.bus.cycle = cycle;
+                    memory.store(memory_address, out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -9,6 +9,16 @@ pub enum Error {
     InvalidCInstructionPadding(usize),
     InvalidCInstructionComp(usize),
     PixelsError(pixels::Error),
+    StateError(String),
+    DebugInfoError(String),
+    TranslationError(String),
+    ConfigError(String),
+    ImageError(String),
+    ScreenMismatch(String),
+    InputScriptError(String),
+    DeviceError(String),
+    Trap(String),
+    RamImageError(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -25,6 +35,24 @@ impl From<pixels::Error> for Error {
     }
 }
 
+impl From<png::EncodingError> for Error {
+    fn from(error: png::EncodingError) -> Self {
+        Error::ImageError(error.to_string())
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(error: png::DecodingError) -> Self {
+        Error::ImageError(error.to_string())
+    }
+}
+
+impl From<gif::EncodingError> for Error {
+    fn from(error: gif::EncodingError) -> Self {
+        Error::ImageError(error.to_string())
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -35,6 +63,16 @@ impl std::fmt::Display for Error {
             Error::InvalidCInstructionPadding(pc) => write!(f, "Invalid C Instruction Padding, should be 111, at PC: {:04X}", pc),
             Error::InvalidCInstructionComp(pc) => write!(f, "Invalid C Instruction Computation, at PC: {:04X}", pc),
             Error::PixelsError(e) => write!(f, "Pixels Error: {}", e),
+            Error::StateError(e) => write!(f, "State Error: {}", e),
+            Error::DebugInfoError(e) => write!(f, "Debug Info Error: {}", e),
+            Error::TranslationError(e) => write!(f, "Translation Error: {}", e),
+            Error::ConfigError(e) => write!(f, "Config Error: {}", e),
+            Error::ImageError(e) => write!(f, "Image Error: {}", e),
+            Error::ScreenMismatch(e) => write!(f, "Screen Mismatch: {}", e),
+            Error::InputScriptError(e) => write!(f, "Input Script Error: {}", e),
+            Error::DeviceError(e) => write!(f, "Device Error: {}", e),
+            Error::Trap(e) => write!(f, "Trap: {}", e),
+            Error::RamImageError(e) => write!(f, "RAM Image Error: {}", e),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,92 @@
+use std::{fs::File, io::BufWriter};
+
+use crate::{Result, Error, Memory, Screen, screen_pixels};
+
+// Checks the screen memory map against a reference image
+//
+// Images are 512x256 PNG files of any color type. Dark pixels stand for set bits, as with
+// the official palette, so a screenshot taken with the default options is a valid reference.
+// Dark pixels of the mask mark regions whose content does not matter.
+pub struct ScreenAssertion {
+    pub expected_path: String,
+    pub diff_path: String,      // Written when the screen does not match
+    expected: Vec<bool>,
+    ignored: Vec<bool>,
+}
+
+impl ScreenAssertion {
+    const MISSING_COLOR: [u8; 3] = [0xFF, 0x00, 0x00];  // Expected set, found clear
+    const EXTRA_COLOR: [u8; 3] = [0x00, 0x00, 0xFF];    // Expected clear, found set
+    const IGNORED_COLOR: [u8; 3] = [0xC0, 0xE0, 0xC0];
+    const SET_COLOR: [u8; 3] = [0x80, 0x80, 0x80];
+    const CLEAR_COLOR: [u8; 3] = [0xFF, 0xFF, 0xFF];
+
+    pub fn new(expected_path: &str, mask_path: Option<&str>, diff_path: String) -> Result<Self> {
+        let expected = Self::load_image(expected_path)?;
+        let ignored = match mask_path {
+            Some(path) => Self::load_image(path)?,
+            None => vec![false; Screen::WIDTH * Screen::HEIGHT],
+        };
+        Ok(Self { expected_path: expected_path.to_string(), diff_path, expected, ignored })
+    }
+
+    // Pixels of a reference image, true for a dark one
+    fn load_image(path: &str) -> Result<Vec<bool>> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        if (info.width as usize, info.height as usize) != (Screen::WIDTH, Screen::HEIGHT) {
+            return Err(Error::ImageError(format!("{} is {}x{}, expected {}x{}", path, info.width, info.height, Screen::WIDTH, Screen::HEIGHT)));
+        }
+
+        let channels = info.color_type.samples();
+        let pixels = buffer[..info.buffer_size()].chunks_exact(channels).map(|pixel| {
+            let luminance = match channels {
+                1 | 2 => pixel[0] as u32,
+                _ => (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000,
+            };
+            luminance < 0x80
+        }).collect();
+        Ok(pixels)
+    }
+
+    // Number of pixels differing from the reference, outside of the mask
+    pub fn mismatches(&self, memory: &Memory) -> usize {
+        screen_pixels(memory).zip(self.expected.iter().zip(self.ignored.iter()))
+            .filter(|(actual, (expected, ignored))| !**ignored && actual != *expected)
+            .count()
+    }
+
+    // Fail when the screen does not match the reference, writing the diff image
+    pub fn check(&self, memory: &Memory) -> Result<()> {
+        let mismatches = self.mismatches(memory);
+        if mismatches == 0 {
+            println!("Screen matches {}", self.expected_path);
+            return Ok(());
+        }
+
+        self.write_diff(memory)?;
+        Err(Error::ScreenMismatch(format!("{} pixels differ from {}, see {}", mismatches, self.expected_path, self.diff_path)))
+    }
+
+    fn write_diff(&self, memory: &Memory) -> Result<()> {
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(&self.diff_path)?), Screen::WIDTH as u32, Screen::HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = screen_pixels(memory).zip(self.expected.iter().zip(self.ignored.iter()))
+            .flat_map(|(actual, (expected, ignored))| match (actual, *expected, *ignored) {
+                (_, _, true) => Self::IGNORED_COLOR,
+                (false, true, _) => Self::MISSING_COLOR,
+                (true, false, _) => Self::EXTRA_COLOR,
+                (true, true, _) => Self::SET_COLOR,
+                (false, false, _) => Self::CLEAR_COLOR,
+            })
+            .collect();
+        encoder.write_header()?.write_image_data(&data)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs;
+
+use crate::{Result, Error, Memory};
+
+// Keyboard register change at a given cycle, before the instruction of that cycle runs
+#[derive(Clone, Copy)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub value: u16,
+}
+
+// Scripted keyboard input, one statement per line or separated by ';'
+//
+//   # Comment
+//   at cycle 1000000 set KBD=131
+//   at cycle 1200000 set KBD=0
+pub struct InputScript {
+    events: Vec<InputEvent>,
+    next: usize,        // Index of the first event not applied yet
+}
+
+impl InputScript {
+    pub fn from_file(path: &str) -> Result<Self> {
+        Self::parse(path, &fs::read_to_string(path)?)
+    }
+
+    // Parse the text of a script, `name` locating the errors
+    pub fn parse(name: &str, text: &str) -> Result<Self> {
+        let mut events: Vec<InputEvent> = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            for statement in line.split(';').map(str::trim).filter(|statement| !statement.is_empty()) {
+                let error = |message: &str| Error::InputScriptError(format!("{}:{}: {} in '{}'", name, index + 1, message, statement));
+                let event = Self::parse_statement(statement).ok_or_else(|| error("expected 'at cycle N set KBD=VALUE'"))?;
+                if events.last().is_some_and(|last| last.cycle > event.cycle) {
+                    return Err(error("cycles must not decrease"));
+                }
+                events.push(event);
+            }
+        }
+        Ok(Self { events, next: 0 })
+    }
+
+    fn parse_statement(statement: &str) -> Option<InputEvent> {
+        let rest = statement.strip_prefix("at")?.trim_start().strip_prefix("cycle")?;
+        let (cycle, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+        let rest = rest.trim_start().strip_prefix("set")?.trim_start().strip_prefix("KBD")?;
+        let value = rest.trim_start().strip_prefix('=')?.trim();
+        Some(InputEvent { cycle: cycle.parse().ok()?, value: value.parse().ok()? })
+    }
+
+    // Cycle of the next event, at which execution must pause to apply it
+    pub fn next_cycle(&self) -> Option<u64> {
+        self.events.get(self.next).map(|event| event.cycle)
+    }
+
+    // Apply the events due at this cycle
+    pub fn apply(&mut self, memory: &mut Memory, cycles: u64) {
+        while let Some(event) = self.events.get(self.next).filter(|event| event.cycle <= cycles) {
+            memory.set_keyboard_reg(event.value);
+            self.next += 1;
+        }
+    }
+}
+
+// Records the keyboard register changes of a session as an input script
+pub struct InputRecorder {
+    path: String,
+    events: Vec<InputEvent>,
+    value: u16,         // Current keyboard register
+}
+
+impl InputRecorder {
+    pub fn new(path: String) -> Self {
+        Self { path, events: Vec::new(), value: 0 }
+    }
+
+    pub fn record(&mut self, cycle: u64, value: u16) {
+        if value != self.value {
+            self.events.push(InputEvent { cycle, value });
+            self.value = value;
+        }
+    }
+
+    // Input script replaying the recorded changes
+    pub fn script(&self) -> String {
+        let mut script = "# Keyboard input recorded by the Hack emulator\n".to_string();
+        for event in self.events.iter() {
+            script.push_str(&format!("at cycle {} set KBD={}\n", event.cycle, event.value));
+        }
+        script
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, self.script())?;
+        println!("Input recorded to {} ({} events)", self.path, self.events.len());
+        Ok(())
+    }
+}
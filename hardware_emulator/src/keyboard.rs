@@ -1,78 +1,164 @@
-use std::collections::HashMap;
+use winit::event::{VirtualKeyCode, ModifiersState, WindowEvent, ElementState, KeyboardInput};
 
+// Translates window keyboard events into Hack keyboard codes
+//
+// Keys producing text get their code from the character received for them, so that the
+// keyboard layout, Shift and Caps Lock apply. The other keys of the Hack keyboard are
+// mapped from their virtual keycode.
+//
+// When several keys are held, the register holds the code of the last pressed key still
+// held: releasing it brings back the code of the previous one, and releasing any other key
+// leaves the register unchanged.
+#[derive(Default)]
 pub struct Keyboard {
     pub reg: u16,                   // Keyboard register
-    pub key_map: HashMap<u32,u16>,  // Map of winit scancodes to Hack keyboard codes
+    pressed: Vec<(u32, u16)>,       // Scancodes and Hack codes of the held keys, in press order
+    pending: Option<u32>,           // Scancode of the last pressed key, waiting for its character
 }
 
 impl Keyboard {
-    pub fn keycode_from_winit(&self, code: u32) -> u16 {
-        match self.key_map.get(&code) {
-            Some(key) => *key,
-            None => 0,
+    pub const NEWLINE: u16 = 128;
+    pub const BACKSPACE: u16 = 129;
+    pub const LEFT_ARROW: u16 = 130;
+    pub const UP_ARROW: u16 = 131;
+    pub const RIGHT_ARROW: u16 = 132;
+    pub const DOWN_ARROW: u16 = 133;
+    pub const HOME: u16 = 134;
+    pub const END: u16 = 135;
+    pub const PAGE_UP: u16 = 136;
+    pub const PAGE_DOWN: u16 = 137;
+    pub const INSERT: u16 = 138;
+    pub const DELETE: u16 = 139;
+    pub const ESCAPE: u16 = 140;
+    pub const F1: u16 = 141;
+
+    // Whether keys pressed with these modifiers are emulator hotkeys rather than Hack input
+    // Windows reports AltGr as Ctrl+Alt, which types characters like @ or { on many layouts
+    pub fn is_hotkey(modifiers: ModifiersState) -> bool {
+        modifiers.ctrl() && !modifiers.alt()
+    }
+
+    // Hack code of the keys which do not produce text
+    pub fn special_key(key: VirtualKeyCode) -> Option<u16> {
+        let code = match key {
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Self::NEWLINE,
+            VirtualKeyCode::Back => Self::BACKSPACE,
+            VirtualKeyCode::Left => Self::LEFT_ARROW,
+            VirtualKeyCode::Up => Self::UP_ARROW,
+            VirtualKeyCode::Right => Self::RIGHT_ARROW,
+            VirtualKeyCode::Down => Self::DOWN_ARROW,
+            VirtualKeyCode::Home => Self::HOME,
+            VirtualKeyCode::End => Self::END,
+            VirtualKeyCode::PageUp => Self::PAGE_UP,
+            VirtualKeyCode::PageDown => Self::PAGE_DOWN,
+            VirtualKeyCode::Insert => Self::INSERT,
+            VirtualKeyCode::Delete => Self::DELETE,
+            VirtualKeyCode::Escape => Self::ESCAPE,
+            VirtualKeyCode::F1 => Self::F1,
+            VirtualKeyCode::F2 => Self::F1 + 1,
+            VirtualKeyCode::F3 => Self::F1 + 2,
+            VirtualKeyCode::F4 => Self::F1 + 3,
+            VirtualKeyCode::F5 => Self::F1 + 4,
+            VirtualKeyCode::F6 => Self::F1 + 5,
+            VirtualKeyCode::F7 => Self::F1 + 6,
+            VirtualKeyCode::F8 => Self::F1 + 7,
+            VirtualKeyCode::F9 => Self::F1 + 8,
+            VirtualKeyCode::F10 => Self::F1 + 9,
+            VirtualKeyCode::F11 => Self::F1 + 10,
+            VirtualKeyCode::F12 => Self::F1 + 11,
+            _ => return None,
+        };
+        Some(code)
+    }
+
+    // Hack code of a printable ASCII character
+    pub fn character_code(character: char) -> Option<u16> {
+        match character {
+            ' '..='~' => Some(character as u16),
+            _ => None,
+        }
+    }
+
+    // Apply a keyboard event, given the modifiers held, returning the key of a hotkey press
+    //
+    // Hotkey presses, and the characters they produce, are not Hack input. Releases always
+    // are, so that a key released while Ctrl is held does not stay pressed.
+    pub fn handle_event(&mut self, event: KeyEvent, modifiers: ModifiersState) -> Option<VirtualKeyCode> {
+        let hotkey = Self::is_hotkey(modifiers);
+        match event {
+            KeyEvent::Pressed(_, key) if hotkey => return key,
+            KeyEvent::Pressed(scancode, key) => self.key_pressed(scancode, key),
+            KeyEvent::Released(scancode) => self.key_released(scancode),
+            KeyEvent::Character(_) if hotkey => {}
+            KeyEvent::Character(character) => self.character_received(character),
+            KeyEvent::FocusLost => self.release_all(),
+        }
+        None
+    }
+
+    pub fn key_pressed(&mut self, scancode: u32, key: Option<VirtualKeyCode>) {
+        match key.and_then(Self::special_key) {
+            Some(code) => {
+                self.pending = None;
+                self.hold(scancode, code);
+            }
+            None => self.pending = Some(scancode),
+        }
+    }
+
+    // Character produced by the last pressed key
+    pub fn character_received(&mut self, character: char) {
+        if let (Some(scancode), Some(code)) = (self.pending.take(), Self::character_code(character)) {
+            self.hold(scancode, code);
         }
     }
+
+    pub fn key_released(&mut self, scancode: u32) {
+        if self.pending == Some(scancode) {
+            self.pending = None;
+        }
+        self.pressed.retain(|(pressed, _)| *pressed != scancode);
+        self.update_reg();
+    }
+
+    // Forget every key, e.g. when the window loses the focus
+    pub fn release_all(&mut self) {
+        self.pending = None;
+        self.pressed.clear();
+        self.update_reg();
+    }
+
+    // Make a key the last pressed one, key repeats included
+    fn hold(&mut self, scancode: u32, code: u16) {
+        self.pressed.retain(|(pressed, _)| *pressed != scancode);
+        self.pressed.push((scancode, code));
+        self.update_reg();
+    }
+
+    fn update_reg(&mut self) {
+        self.reg = self.pressed.last().map_or(0, |(_, code)| *code);
+    }
+}
+
+// Keyboard part of a window event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed(u32, Option<VirtualKeyCode>),   // Scancode and virtual keycode, key repeats included
+    Released(u32),
+    Character(char),
+    FocusLost,
 }
 
-impl Default for Keyboard {
-    fn default() -> Self {
-        let mut key_map = HashMap::new();
-        
-        key_map.insert(1, 140);
-        key_map.insert(2, '1' as u16);      // 1
-        key_map.insert(3, '2' as u16);      // 2
-        key_map.insert(4, '3' as u16);      // 3
-        key_map.insert(5, '4' as u16);      // 4 
-        key_map.insert(6, '5' as u16);      // 5
-        key_map.insert(7, '6' as u16);      // 6
-        key_map.insert(8, '7' as u16);      // 7
-        key_map.insert(9, '8' as u16);      // 8
-        key_map.insert(10, '9' as u16);     // 9
-        key_map.insert(11, '0' as u16);     // 0
-        key_map.insert(12, '-' as u16);     // -
-        key_map.insert(13, '=' as u16);     // =
-        key_map.insert(14, 129);            // Backspace
-        key_map.insert(16, 'q' as u16);     // q
-        key_map.insert(17, 'w' as u16);     // w
-        key_map.insert(18, 'e' as u16);     // e
-        key_map.insert(19, 'r' as u16);     // r
-        key_map.insert(20, 't' as u16);     // t
-        key_map.insert(21, 'y' as u16);     // y
-        key_map.insert(22, 'u' as u16);     // u
-        key_map.insert(23, 'i' as u16);     // i
-        key_map.insert(24, 'o' as u16);     // o
-        key_map.insert(25, 'p' as u16);     // p
-        key_map.insert(26, '[' as u16);     // [
-        key_map.insert(27, ']' as u16);     // ]
-        key_map.insert(28, 128);            // Enter
-        key_map.insert(30, 'a' as u16);     // a
-        key_map.insert(31, 's' as u16);     // s
-        key_map.insert(32, 'd' as u16);     // d
-        key_map.insert(33, 'f' as u16);     // f
-        key_map.insert(34, 'g' as u16);     // g
-        key_map.insert(35, 'h' as u16);     // h
-        key_map.insert(36, 'j' as u16);     // j
-        key_map.insert(37, 'k' as u16);     // k
-        key_map.insert(38, 'l' as u16);     // l
-        key_map.insert(39, ';' as u16);     // ;
-        key_map.insert(40, '\'' as u16);    // '
-        key_map.insert(41, '`' as u16);     // `
-        key_map.insert(43, '\\' as u16);    // \
-        key_map.insert(44, 'z' as u16);     // z
-        key_map.insert(45, 'x' as u16);     // x
-        key_map.insert(46, 'c' as u16);     // c
-        key_map.insert(47, 'v' as u16);     // v
-        key_map.insert(48, 'b' as u16);     // b
-        key_map.insert(49, 'n' as u16);     // n
-        key_map.insert(50, 'm' as u16);     // m
-        key_map.insert(51, ',' as u16);     // ,
-        key_map.insert(52, '.' as u16);     // .
-        key_map.insert(53, '/' as u16);     // /
-        key_map.insert(57416, 131);         // Up arrow
-        key_map.insert(57419, 130);         // Left arrow
-        key_map.insert(57421, 132);         // Right arrow
-        key_map.insert(57424, 133);         // Down arrow
-        
-        Self { reg: 0, key_map }
+impl KeyEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, scancode, virtual_keycode, .. }, .. } =>
+                Some(Self::Pressed(*scancode, *virtual_keycode)),
+            WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Released, scancode, .. }, .. } =>
+                Some(Self::Released(*scancode)),
+            WindowEvent::ReceivedCharacter(character) => Some(Self::Character(*character)),
+            WindowEvent::Focused(false) => Some(Self::FocusLost),
+            _ => None,
+        }
     }
-}
\ No newline at end of file
+}
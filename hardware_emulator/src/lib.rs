@@ -0,0 +1,85 @@
+// Hack computer emulator library, used by the emulator binary, the tools and the benchmarks
+
+mod emulator;
+pub use emulator::*;
+
+mod error;
+pub use error::*;
+
+mod keyboard;
+pub use keyboard::*;
+
+mod screen;
+pub use screen::*;
+
+mod display;
+pub use display::*;
+
+mod cpu;
+pub use cpu::*;
+
+mod cpu_model;
+pub use cpu_model::*;
+
+mod rom;
+pub use rom::*;
+
+mod memory;
+pub use memory::*;
+
+mod device;
+pub use device::*;
+
+mod audio;
+pub use audio::*;
+
+mod disk_image;
+pub use disk_image::*;
+
+mod disk;
+pub use disk::*;
+
+mod state;
+pub use state::*;
+
+mod debug_info;
+pub use debug_info::*;
+
+mod profiler;
+pub use profiler::*;
+
+mod coverage;
+pub use coverage::*;
+
+mod clock;
+pub use clock::*;
+
+mod engine;
+pub use engine::*;
+
+mod benchmark;
+pub use benchmark::*;
+
+mod translator;
+pub use translator::*;
+
+mod capture;
+pub use capture::*;
+
+mod golden;
+pub use golden::*;
+
+mod input_script;
+pub use input_script::*;
+
+mod machine_init;
+pub use machine_init::*;
+
+mod shadow;
+pub use shadow::*;
+
+mod protection;
+pub use protection::*;
+
+mod statistics;
+pub use statistics::*;
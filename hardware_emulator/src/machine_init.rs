@@ -0,0 +1,328 @@
+use std::{fs, str::FromStr, collections::BTreeMap};
+
+use crate::{Result, Error, Cpu, Memory, Rom};
+
+// What the reset hotkey does besides sending the PC back to the start address
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    Pc,     // Nothing else, like the reset input of the Hack CPU
+    Ram,    // Clear the RAM and initialize the machine again
+    All,    // Clear the screen memory map too
+}
+
+impl FromStr for ResetMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        match text {
+            "pc" => Ok(Self::Pc),
+            "ram" => Ok(Self::Ram),
+            "all" => Ok(Self::All),
+            _ => Err(format!("Invalid reset mode '{}', expected pc, ram or all", text)),
+        }
+    }
+}
+
+// Registers and RAM words set when the machine starts and when it is reset
+//
+// Settings are key = value pairs, applied in order:
+//   preset = vm-bootstrap      start over from a preset, bare or vm-bootstrap
+//   A = 0, D = 0, PC = 0       registers, PC being the start address
+//   SP = 256, R13 = 0          RAM words by their assembler symbol, SP to THAT and R0 to R15
+//   RAM[1000] = 0x7FFF         RAM words by address
+//   reset = ram                what the reset hotkey clears: pc, ram or all
+// Values are decimal, negative decimal or 0x hexadecimal. RAM images are loaded before the
+// RAM settings, which override them.
+#[derive(Clone)]
+pub struct MachineInit {
+    ram: BTreeMap<usize, u16>,
+    images: Vec<(usize, Vec<u16>)>,     // RAM images with their load address
+    reg_a: Option<u16>,                 // 0 when not set
+    reg_d: Option<u16>,
+    pc: usize,
+    pub reset: ResetMode,
+}
+
+impl Default for MachineInit {
+    fn default() -> Self {
+        Self::bare()
+    }
+}
+
+impl MachineInit {
+    pub const DATA_IMAGE_ADDRESS: usize = 16;     // Start of the RAM image written by the assembler
+    const SYMBOLS: [(&'static str, usize); 5] = [
+        ("SP", Memory::SP), ("LCL", Memory::LCL), ("ARG", Memory::ARG), ("THIS", Memory::THIS), ("THAT", Memory::THAT),
+    ];
+
+    // Everything at 0, like the Hack hardware
+    pub fn bare() -> Self {
+        Self { ram: BTreeMap::new(), images: Vec::new(), reg_a: None, reg_d: None, pc: 0, reset: ResetMode::Pc }
+    }
+
+    // Stack and segment pointers of the VM, with the values of the nand2tetris test scripts,
+    // for programs translated without the bootstrap code
+    pub fn vm_bootstrap() -> Self {
+        let mut init = Self::bare();
+        init.ram.extend([(Memory::SP, 256), (Memory::LCL, 300), (Memory::ARG, 400), (Memory::THIS, 3000), (Memory::THAT, 3010)]);
+        init
+    }
+
+    // Apply the settings of a file, one per line
+    pub fn load_file(&mut self, path: &str) -> Result<()> {
+        for (index, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: String| Error::ConfigError(format!("{}:{}: {}", path, index + 1, message));
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected key = value".to_string()))?;
+            self.set(key.trim(), value.trim()).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    // Apply a KEY=VALUE setting given on the command line
+    pub fn set_arg(&mut self, setting: &str) -> Result<()> {
+        let error = |message: String| Error::ConfigError(format!("--init {}: {}", setting, message));
+        let (key, value) = setting.split_once('=').ok_or_else(|| error("expected KEY=VALUE".to_string()))?;
+        self.set(key.trim(), value.trim()).map_err(error)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> std::result::Result<(), String> {
+        match key {
+            "preset" => {
+                let preset = match value {
+                    "bare" => Self::bare(),
+                    "vm-bootstrap" => Self::vm_bootstrap(),
+                    _ => return Err(format!("Invalid preset '{}', expected bare or vm-bootstrap", value)),
+                };
+                *self = Self { images: std::mem::take(&mut self.images), reset: self.reset, ..preset };
+            }
+            "reset" => self.reset = value.parse()?,
+            "A" => self.reg_a = Some(Self::parse_word(value)?),
+            "D" => self.reg_d = Some(Self::parse_word(value)?),
+            "PC" => {
+                let pc = Self::parse_word(value)? as usize;
+                if pc >= Rom::ROM_SIZE {
+                    return Err(format!("PC {} outside of the ROM", pc));
+                }
+                self.pc = pc;
+            }
+            key => {
+                let address = Self::ram_address(key).ok_or_else(|| format!("unknown setting '{}'", key))?;
+                self.ram.insert(address, Self::parse_word(value)?);
+            }
+        }
+        Ok(())
+    }
+
+    // Address of SP to THAT, R0 to R15 or RAM[ADDRESS]
+    fn ram_address(key: &str) -> Option<usize> {
+        if let Some((_, address)) = Self::SYMBOLS.iter().find(|(symbol, _)| *symbol == key) {
+            return Some(*address);
+        }
+        if let Some(register) = key.strip_prefix('R').and_then(|number| number.parse::<usize>().ok()) {
+            return (register < 16).then_some(register);
+        }
+        let address = key.strip_prefix("RAM[")?.strip_suffix(']')?;
+        Self::parse_word(address).ok().map(|address| address as usize).filter(|address| *address < Memory::SCREEN_MEMORY_MAP_OFFSET)
+    }
+
+    fn parse_word(text: &str) -> std::result::Result<u16, String> {
+        let value = match text.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => text.parse::<u16>().ok().or_else(|| text.parse::<i16>().ok().map(|value| value as u16)),
+        };
+        value.ok_or_else(|| format!("Invalid value '{}', expected a 16-bit decimal or 0x hexadecimal number", text))
+    }
+
+    // Load a RAM image given as FILE@ADDRESS on the command line
+    pub fn load_image_arg(&mut self, spec: &str) -> Result<()> {
+        let error = |message: String| Error::RamImageError(format!("{}: {}", spec, message));
+        let (path, address) = spec.rsplit_once('@').ok_or_else(|| error("expected FILE@ADDRESS".to_string()))?;
+        let address = Self::parse_word(address).map_err(error)?;
+        self.load_image(path, address as usize)
+    }
+
+    // Load a RAM image, whose words go to consecutive addresses up to the end of the screen
+    // memory map. Files ending with .txt hold whitespace or comma separated values, with //
+    // comments, binary files big-endian words like the .hack files.
+    pub fn load_image(&mut self, path: &str, address: usize) -> Result<()> {
+        let error = |message: String| Error::RamImageError(format!("{}: {}", path, message));
+        let words = if path.ends_with(".txt") {
+            Self::parse_text_image(&fs::read_to_string(path)?)
+        } else {
+            Self::parse_binary_image(&fs::read(path)?)
+        }.map_err(error)?;
+
+        if address + words.len() > Memory::KEYBOARD_REG_OFFSET {
+            return Err(error(format!("{} words at {:04X} go past the screen memory map", words.len(), address)));
+        }
+        println!("Loaded RAM image {} at {:04X}-{:04X}", path, address, address + words.len().max(1) - 1);
+        self.images.push((address, words));
+        Ok(())
+    }
+
+    fn parse_text_image(text: &str) -> std::result::Result<Vec<u16>, String> {
+        let mut words = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap();
+            for value in line.split(|c: char| c.is_whitespace() || c == ',').filter(|value| !value.is_empty()) {
+                words.push(Self::parse_word(value).map_err(|message| format!("line {}: {}", index + 1, message))?);
+            }
+        }
+        Ok(words)
+    }
+
+    fn parse_binary_image(bytes: &[u8]) -> std::result::Result<Vec<u16>, String> {
+        if !bytes.len().is_multiple_of(2) {
+            return Err("size must be a multiple of 2".to_string());
+        }
+        Ok(bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect())
+    }
+
+    pub fn start_pc(&self) -> usize {
+        self.pc
+    }
+
+    // RAM addresses given a value, by an image or a setting
+    pub fn initialized_addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        let images = self.images.iter().flat_map(|(address, words)| *address..*address + words.len());
+        images.chain(self.ram.keys().copied())
+    }
+
+    pub fn sets_registers(&self) -> (bool, bool) {
+        (self.reg_a.is_some(), self.reg_d.is_some())
+    }
+
+    pub fn apply(&self, cpu: &mut Cpu, memory: &mut Memory) -> Result<()> {
+        cpu.reg_a = self.reg_a.unwrap_or(0);
+        cpu.reg_d = self.reg_d.unwrap_or(0);
+        cpu.pc = self.pc;
+        for (address, words) in self.images.iter() {
+            for (offset, word) in words.iter().enumerate() {
+                memory.poke(address + offset, *word)?;
+            }
+        }
+        for (address, value) in self.ram.iter() {
+            memory.poke(*address, *value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(init: &MachineInit) -> (Cpu, Memory) {
+        let (mut cpu, mut memory) = (Cpu::new(), Memory::new());
+        init.apply(&mut cpu, &mut memory).unwrap();
+        (cpu, memory)
+    }
+
+    #[test]
+    fn parse_word() {
+        assert_eq!(MachineInit::parse_word("1000"), Ok(1000));
+        assert_eq!(MachineInit::parse_word("65535"), Ok(0xFFFF));
+        assert_eq!(MachineInit::parse_word("-1"), Ok(0xFFFF));
+        assert_eq!(MachineInit::parse_word("0x7FFF"), Ok(0x7FFF));
+        for text in ["65536", "-32769", "0x10000", "0X10", "ten", ""] {
+            assert!(MachineInit::parse_word(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn ram_address() {
+        assert_eq!(MachineInit::ram_address("SP"), Some(0));
+        assert_eq!(MachineInit::ram_address("THAT"), Some(4));
+        assert_eq!(MachineInit::ram_address("R0"), Some(0));
+        assert_eq!(MachineInit::ram_address("R15"), Some(15));
+        assert_eq!(MachineInit::ram_address("RAM[1000]"), Some(1000));
+        assert_eq!(MachineInit::ram_address("RAM[0x3FFF]"), Some(0x3FFF));
+        for key in ["R16", "RAM[0x4000]", "RAM[1000", "RAM[-1]", "sp", "PC"] {
+            assert_eq!(MachineInit::ram_address(key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn bare_preset() {
+        let (cpu, memory) = applied(&MachineInit::bare());
+        assert_eq!((cpu.reg_a, cpu.reg_d, cpu.pc), (0, 0, 0));
+        assert!(memory.words.iter().all(|word| *word == 0));
+    }
+
+    #[test]
+    fn vm_bootstrap_preset() {
+        let (_, memory) = applied(&MachineInit::vm_bootstrap());
+        assert_eq!(memory.words[..5], [256, 300, 400, 3000, 3010]);
+        assert!(memory.words[5..].iter().all(|word| *word == 0));
+    }
+
+    #[test]
+    fn preset_starts_over_keeping_reset_mode() {
+        let mut init = MachineInit::bare();
+        for (key, value) in [("SP", "261"), ("R13", "7"), ("reset", "all"), ("preset", "vm-bootstrap"), ("LCL", "500")] {
+            init.set(key, value).unwrap();
+        }
+        let (_, memory) = applied(&init);
+        assert_eq!(memory.words[..5], [256, 500, 400, 3000, 3010]);
+        assert_eq!(memory.words[13], 0);
+        assert!(init.reset == ResetMode::All);
+        assert!(init.set("preset", "vm").is_err());
+    }
+
+    #[test]
+    fn registers_and_start_address() {
+        let mut init = MachineInit::bare();
+        for (key, value) in [("A", "0x4000"), ("D", "-2"), ("PC", "100")] {
+            init.set(key, value).unwrap();
+        }
+        let (cpu, _) = applied(&init);
+        assert_eq!((cpu.reg_a, cpu.reg_d, cpu.pc), (0x4000, 0xFFFE, 100));
+        assert!(init.set("PC", "32768").is_err());
+        assert!(init.set("X", "1").is_err());
+    }
+
+    #[test]
+    fn reset_mode() {
+        assert!("pc".parse::<ResetMode>() == Ok(ResetMode::Pc));
+        assert!("ram".parse::<ResetMode>() == Ok(ResetMode::Ram));
+        assert!("all".parse::<ResetMode>() == Ok(ResetMode::All));
+        assert!("screen".parse::<ResetMode>().is_err());
+        assert!(MachineInit::default().reset == ResetMode::Pc);
+    }
+
+    #[test]
+    fn text_image() {
+        let text = "1, 2 0x7FFF // Comment, 4\n\n-1,\t-32768\n";
+        assert_eq!(MachineInit::parse_text_image(text), Ok(vec![1, 2, 0x7FFF, 0xFFFF, 0x8000]));
+        assert_eq!(MachineInit::parse_text_image(""), Ok(vec![]));
+        assert!(MachineInit::parse_text_image("1\n2 x").unwrap_err().starts_with("line 2:"));
+    }
+
+    #[test]
+    fn binary_image() {
+        assert_eq!(MachineInit::parse_binary_image(&[0x00, 0x01, 0x12, 0x34, 0xFF, 0xFF]), Ok(vec![1, 0x1234, 0xFFFF]));
+        assert!(MachineInit::parse_binary_image(&[0x00, 0x01, 0x12]).is_err());
+    }
+
+    #[test]
+    fn image_argument() {
+        let mut init = MachineInit::bare();
+        for spec in ["table.txt", "table.txt@", "table.txt@ram", "table.txt@65536"] {
+            assert!(matches!(init.load_image_arg(spec), Err(Error::RamImageError(_))), "{}", spec);
+        }
+        assert!(matches!(init.load_image_arg("missing.txt@16"), Err(Error::IoError(_))));
+    }
+
+    #[test]
+    fn image_outside_of_the_memory_map_is_an_error() {
+        let mut init = MachineInit::bare();
+        init.images.push((Memory::KEYBOARD_REG_OFFSET, vec![1, 2]));
+        let (mut cpu, mut memory) = (Cpu::new(), Memory::new());
+        assert!(matches!(init.apply(&mut cpu, &mut memory), Err(Error::InvalidMemoryAddress(0x6001))));
+    }
+}
@@ -1,27 +1,10 @@
+use std::path::Path;
+
 use pixels::SurfaceTexture;
-use winit::{event_loop::EventLoop, dpi::LogicalSize, window::WindowBuilder};
+use winit::{event_loop::EventLoop, dpi::LogicalSize, window::{WindowBuilder, Fullscreen}};
 use clap::{self, Parser};
 
-mod emulator;
-pub use emulator::*;
-
-mod error;
-pub use error::*;
-
-mod keyboard;
-pub use keyboard::*;
-
-mod screen;
-pub use screen::*;
-
-mod cpu;
-pub use cpu::*;
-
-mod rom;
-pub use rom::*;
-
-mod memory;
-pub use memory::*;
+use hardware_emulator::*;
 
 #[derive(Parser)]
 #[command(author = "Obeeron", version = env!("CARGO_PKG_VERSION"), about="A Hack Computer emulator")]
@@ -29,19 +12,113 @@ struct Cli {
     hack_file: String,
     #[clap(short, long="cpy-cycle-per-sec", default_value = "2000000", help = "Number of CPU cycles per second")]
     cpu_cycles_per_sec: u32,
+    #[clap(long = "init-file", value_name = "FILE", help = "Machine initialization file, with key = value lines for preset, A, D, PC, RAM words and reset")]
+    init_file: Option<String>,
+    #[clap(long = "init", value_name = "KEY=VALUE", value_delimiter = ',', help = "Machine initialization setting applied after --init-file, like preset=vm-bootstrap, SP=256 or reset=ram, can be repeated or comma separated")]
+    init: Vec<String>,
+    #[clap(long = "ram-image", value_name = "FILE@ADDRESS", help = "Load words into RAM at start and on reset, from a big-endian binary file or a .txt file of numbers, can be repeated [default: <HACK_FILE>.ram at 16 if it exists]")]
+    ram_images: Vec<String>,
+    #[clap(long="load-state", help = "Snapshot file to restore before starting")]
+    load_state: Option<String>,
+    #[clap(long="state-file", help = "Snapshot file used by the Ctrl+S/Ctrl+L hotkeys [default: <HACK_FILE>.state]")]
+    state_file: Option<String>,
+    #[clap(long, requires = "cycles", help = "Run without a window, as fast as possible")]
+    headless: bool,
+    #[clap(long, help = "Stop after executing this many instructions")]
+    cycles: Option<u64>,
+    #[clap(long="debug-info", help = "Debug information file produced by the assembler [default: <HACK_FILE>.dbg if it exists]")]
+    debug_info: Option<String>,
+    #[clap(long, value_name = "PREFIX", help = "Profile the run, writing a hot-spot report to PREFIX.txt and folded stacks to PREFIX.folded")]
+    profile: Option<String>,
+    #[clap(long, value_name = "FILE", help = "Record instruction and branch coverage, writing an lcov tracefile to FILE")]
+    coverage: Option<String>,
+    #[clap(long, help = "Run a pre-decoded copy of the ROM instead of decoding every instruction")]
+    fast: bool,
+    #[clap(long, help = "Start in turbo mode, ignoring the CPU cycles per second")]
+    unlimited: bool,
+    #[clap(long, requires = "cycles", help = "Compare the speed of the interpreter and the fast engine over --cycles instructions")]
+    benchmark: bool,
+    #[clap(long, value_name = "FILE", help = "Translate the ROM to a standalone Rust program written to FILE")]
+    translate: Option<String>,
+    #[clap(long="verify-translation", requires = "cycles", help = "Translate the ROM, compile it with rustc, and compare it with the interpreter over --cycles instructions")]
+    verify_translation: bool,
+    #[clap(long, value_name = "FILE", help = "Display options file, with key = value lines for palette, scaling, filter, grid and fullscreen")]
+    display_config: Option<String>,
+    #[clap(long, help = "Screen colors: official (1 is black), inverted, green, amber, or ON:OFF in RRGGBB hexadecimal")]
+    palette: Option<Palette>,
+    #[clap(long, help = "Scale the screen by whole multiples (integer) or as large as the window allows (fit)")]
+    scaling: Option<Scaling>,
+    #[clap(long, help = "Filter used when scaling the screen: nearest or smooth")]
+    filter: Option<Filter>,
+    #[clap(long, help = "Overlay a grid between the Hack pixels when they are large enough")]
+    grid: bool,
+    #[clap(long, help = "Start in fullscreen")]
+    fullscreen: bool,
+    #[clap(long, value_name = "FILE", help = "Save a PNG image of the screen when the emulator stops")]
+    screenshot: Option<String>,
+    #[clap(long, value_name = "FILE", help = "Record the screen from the start, as an animated GIF if FILE ends with .gif, as raw 1-bit frames otherwise")]
+    record: Option<String>,
+    #[clap(long = "record-fps", value_name = "FPS", default_value_t = Emulator::DEFAULT_RECORD_FPS, help = "Frames per second of emulated time in recordings")]
+    record_fps: u32,
+    #[clap(long = "expect-screen", value_name = "FILE", help = "Fail unless the screen matches this 512x256 PNG image when the emulator stops, dark pixels being set bits")]
+    expect_screen: Option<String>,
+    #[clap(long = "screen-mask", value_name = "FILE", requires = "expect_screen", help = "PNG image whose dark pixels are ignored by --expect-screen")]
+    screen_mask: Option<String>,
+    #[clap(long = "screen-diff", value_name = "FILE", requires = "expect_screen", help = "Image showing the differing pixels, written when --expect-screen fails [default: <FILE>.diff.png]")]
+    screen_diff: Option<String>,
+    #[clap(long = "input-script", value_name = "FILE", help = "Replay keyboard input from a script of 'at cycle N set KBD=VALUE' statements")]
+    input_script: Option<String>,
+    #[clap(long = "record-input", value_name = "FILE", help = "Record the keyboard input of the session as an input script")]
+    record_input: Option<String>,
+    #[clap(long = "device", value_name = "NAME@ADDRESS[:FILE]", help = "Attach a memory-mapped device above the keyboard register, can be repeated. Devices: rng, console, timer, audio, disk")]
+    devices: Vec<String>,
+    #[clap(long = "trap", value_name = "POLICY", value_delimiter = ',', help = "Stop with an error on faults: kbd-write, uninit-read, stack-overflow, rom-jump, can be repeated or comma separated")]
+    traps: Vec<TrapPolicy>,
+    #[clap(long = "stack-limit", value_name = "ADDRESS", default_value_t = Protection::DEFAULT_STACK_LIMIT, help = "Highest SP allowed by --trap stack-overflow")]
+    stack_limit: u16,
+    #[clap(long = "shadow-memory", help = "Warn about reads of RAM words and registers never written since start")]
+    shadow_memory: bool,
+    #[clap(long, help = "Print instruction, branch and memory access statistics when the emulator stops")]
+    stats: bool,
+    #[clap(long = "cpu-model", value_name = "MODEL", help = "CPU timing model reporting clock cycles, stalls and hazards: single-cycle, pipeline[:STAGES] with 2 to 5 stages")]
+    cpu_model: Option<CpuModelKind>,
 }
 
 // Takes a path to a .hack file and executes it
 fn main() -> Result<()>{
     let cli = Cli::parse();
-    if cli.hack_file == "" {
+    if cli.hack_file.is_empty() {
         println!("No input file specified");
         return Ok(());
     }
 
+    if cli.benchmark {
+        return run_benchmark(&cli.hack_file, cli.cycles.unwrap());
+    }
+
     // Get ROM from binary file
     let rom = Rom::from_file(&cli.hack_file)?;
 
+    let state_path = cli.state_file.clone().unwrap_or_else(|| {
+        Path::new(&cli.hack_file).with_extension("state").to_string_lossy().into_owned()
+    });
+
+    if let Some(path) = &cli.translate {
+        let emulator = create_emulator(&cli, rom, None, state_path)?;
+        return Translator::new(&emulator).write(path);
+    }
+    if cli.verify_translation {
+        let emulator = create_emulator(&cli, rom, None, state_path)?;
+        return verify_translation(emulator, cli.cycles.unwrap());
+    }
+
+    if cli.headless {
+        let emulator = create_emulator(&cli, rom, None, state_path)?;
+        return emulator.run_headless();
+    }
+
+    let display_options = display_options(&cli)?;
+
     // Create Event Loop
     let event_loop = EventLoop::new();
     
@@ -52,14 +129,123 @@ fn main() -> Result<()>{
         .with_title("Hack Computer Emulator")
         .with_inner_size(scaled_screen_size)
         .with_min_inner_size(screen_size)
+        .with_fullscreen(display_options.fullscreen.then_some(Fullscreen::Borderless(None)))
         .build(&event_loop)
         .unwrap();
-    let surface_texture = SurfaceTexture::new(window.inner_size().width, window.inner_size().height, &window);
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
     
-    // Create a new Pixel buffer
-    let pixels = pixels::Pixels::new(512, 256, surface_texture).unwrap();
+    // Create a new Pixel buffer, scaled to the window by the Screen
+    let pixels = pixels::Pixels::new(window_size.width, window_size.height, surface_texture).unwrap();
+    let screen = Screen::new(pixels, display_options, window_size.width, window_size.height);
 
     // Create and run emulator
-    let emulator = Emulator::new(rom, pixels);
-    emulator.run(event_loop, window, cli.cpu_cycles_per_sec)
+    let emulator = create_emulator(&cli, rom, Some(screen), state_path)?;
+    emulator.run(event_loop, window)
+}
+
+// Display options from the config file, overridden by the command line
+fn display_options(cli: &Cli) -> Result<DisplayOptions> {
+    let mut options = match &cli.display_config {
+        Some(path) => DisplayOptions::from_file(path)?,
+        None => DisplayOptions::default(),
+    };
+    if let Some(palette) = cli.palette {
+        options.palette = palette;
+    }
+    if let Some(scaling) = cli.scaling {
+        options.scaling = scaling;
+    }
+    if let Some(filter) = cli.filter {
+        options.filter = filter;
+    }
+    options.grid |= cli.grid;
+    options.fullscreen |= cli.fullscreen;
+    Ok(options)
+}
+
+fn create_emulator(cli: &Cli, rom: Rom, screen: Option<Screen>, state_path: String) -> Result<Emulator> {
+    let mut emulator = Emulator::new(rom, screen, state_path);
+    emulator.max_cycles = cli.cycles;
+
+    let mut init = MachineInit::default();
+    let data_image = Path::new(&cli.hack_file).with_extension("ram");
+    if data_image.exists() {
+        init.load_image(&data_image.to_string_lossy(), MachineInit::DATA_IMAGE_ADDRESS)?;
+    }
+    for spec in cli.ram_images.iter() {
+        init.load_image_arg(spec)?;
+    }
+    if let Some(path) = &cli.init_file {
+        init.load_file(path)?;
+    }
+    for setting in cli.init.iter() {
+        init.set_arg(setting)?;
+    }
+    emulator.set_init(init)?;
+
+    for spec in cli.devices.iter() {
+        let (address, device) = parse_device(spec, cli.cpu_cycles_per_sec)?;
+        emulator.memory.bus.attach(address, device)?;
+    }
+
+    let default_debug_info = Path::new(&cli.hack_file).with_extension("dbg");
+    if let Some(path) = &cli.debug_info {
+        emulator.debug_info = DebugInfo::from_file(path)?;
+    } else if default_debug_info.exists() {
+        emulator.debug_info = DebugInfo::from_file(&default_debug_info.to_string_lossy())?;
+    }
+
+    if let Some(prefix) = &cli.profile {
+        emulator.profiler = Some(Profiler::new(prefix.clone()));
+    }
+    if let Some(path) = &cli.coverage {
+        emulator.coverage = Some(Coverage::new(path.clone()));
+    }
+    if !cli.traps.is_empty() {
+        let program_size = emulator.rom.size();
+        emulator.protection = Some(Protection::new(cli.traps.clone(), cli.stack_limit, program_size, &emulator.init));
+    }
+    if cli.shadow_memory {
+        emulator.uninitialized_reads = Some(UninitializedReads::new(&emulator.init));
+    }
+    if cli.stats {
+        emulator.statistics = Some(Statistics::new());
+    }
+    if let Some(model) = cli.cpu_model {
+        emulator.cpu_model = Some(model.create()?);
+    }
+
+    if cli.fast {
+        emulator.enable_fast_engine();
+    }
+    emulator.clock = Clock::new(cli.cpu_cycles_per_sec);
+    if cli.unlimited {
+        emulator.clock.mode = SpeedMode::Turbo;
+    }
+
+    if let Some(path) = &cli.load_state {
+        emulator.load_state(path)?;
+    }
+
+    emulator.palette = display_options(cli)?.palette;
+    emulator.record_fps = cli.record_fps;
+    emulator.screenshot_path = cli.screenshot.clone();
+    emulator.capture_prefix = Path::new(&cli.hack_file).with_extension("").to_string_lossy().into_owned();
+    if let Some(path) = &cli.input_script {
+        emulator.input_script = Some(InputScript::from_file(path)?);
+    }
+    if let Some(path) = &cli.record_input {
+        emulator.input_recorder = Some(InputRecorder::new(path.clone()));
+    }
+    if let Some(path) = &cli.expect_screen {
+        let diff_path = cli.screen_diff.clone().unwrap_or_else(|| {
+            Path::new(path).with_extension("diff.png").to_string_lossy().into_owned()
+        });
+        emulator.screen_assertion = Some(ScreenAssertion::new(path, cli.screen_mask.as_deref(), diff_path)?);
+    }
+    if let Some(path) = &cli.record {
+        emulator.start_recording(path.clone())?;
+    }
+    Ok(emulator)
 }
@@ -1,38 +1,103 @@
-use crate::{Result, Error};
+use crate::{Result, Bus};
 
-pub struct Memory (pub Box<[u16; Memory::TOTAL_SIZE]>);  // Data memory and Screen memory map
+// Data memory: RAM in a flat array, then the device bus for the addresses above it, where
+// the screen memory map and the keyboard register are attached like any other device
+pub struct Memory {
+    pub words: Box<[u16; Memory::RAM_SIZE]>,
+    pub bus: Bus,       // Screen and keyboard only by default, keeping strict Hack compatibility
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Memory {
-    pub const TOTAL_SIZE: usize = 0x6001;
+    pub const RAM_SIZE: usize = 0x4000;
+    pub const TOTAL_SIZE: usize = 0x6001;   // RAM, screen and keyboard, the data memory of the Hack computer
     pub const SCREEN_MEMORY_MAP_OFFSET: usize = 0x4000;
     pub const SCREEN_MEMORY_MAP_SIZE: usize = 0x2000;
     pub const KEYBOARD_REG_OFFSET: usize = 0x6000;
 
+    // RAM words holding the stack and segment pointers of the VM
+    pub const SP: usize = 0;
+    pub const LCL: usize = 1;
+    pub const ARG: usize = 2;
+    pub const THIS: usize = 3;
+    pub const THAT: usize = 4;
+
     pub fn new() -> Self {
-        Self(Box::new([0; Self::TOTAL_SIZE]))
+        Self {
+            words: Box::new([0; Self::RAM_SIZE]),
+            bus: Bus::new(),
+        }
     }
 
-    pub fn fetch(&self, address: usize) -> Result<u16> {
-        if address >= Self::TOTAL_SIZE {
-            return Err(Error::InvalidMemoryAddress(address));
+    pub fn fetch(&mut self, address: usize) -> Result<u16> {
+        match self.words.get(address) {
+            Some(value) => Ok(*value),
+            None => self.bus.read(address),
         }
-        Ok(self.0[address])
     }
 
     pub fn store(&mut self, address: usize, value: u16) -> Result<()> {
-        if address >= Self::TOTAL_SIZE {
-            return Err(Error::InvalidMemoryAddress(address));
+        match self.words.get_mut(address) {
+            Some(word) => *word = value,
+            None => self.bus.write(address, value)?,
         }
-        self.0[address] = value;
+        Ok(())
+    }
 
+    // Read without side effects, devices which are not plain memory excluded
+    pub fn peek(&self, address: usize) -> Result<u16> {
+        match self.words.get(address) {
+            Some(value) => Ok(*value),
+            None => self.bus.peek(address),
+        }
+    }
+
+    // Write without side effects, devices which are not plain memory excluded
+    pub fn poke(&mut self, address: usize, value: u16) -> Result<()> {
+        match self.words.get_mut(address) {
+            Some(word) => *word = value,
+            None => self.bus.poke(address, value)?,
+        }
         Ok(())
     }
-   
+
+    // Whether RAM or a device answers at `address`
+    pub fn is_mapped(&self, address: usize) -> bool {
+        address < Self::RAM_SIZE || self.bus.is_mapped(address)
+    }
+
+    pub fn screen(&self) -> &[u16] {
+        self.bus.memory(Self::SCREEN_MEMORY_MAP_OFFSET).expect("Screen memory map attached")
+    }
+
+    pub fn screen_mut(&mut self) -> &mut [u16] {
+        self.bus.memory_mut(Self::SCREEN_MEMORY_MAP_OFFSET).expect("Screen memory map attached")
+    }
+
     pub fn set_keyboard_reg(&mut self, keycode: u16) {
-        self.0[Self::KEYBOARD_REG_OFFSET] = keycode;
+        self.bus.memory_mut(Self::KEYBOARD_REG_OFFSET).expect("Keyboard register attached")[0] = keycode;
+    }
+
+    // RAM, screen and keyboard words, as saved in snapshots
+    pub fn data_words(&self) -> Vec<u16> {
+        let keyboard = self.bus.memory(Self::KEYBOARD_REG_OFFSET).expect("Keyboard register attached");
+        [&self.words[..], self.screen(), keyboard].concat()
+    }
+
+    pub fn set_data_words(&mut self, words: &[u16]) {
+        let (ram, rest) = words.split_at(Self::RAM_SIZE);
+        let (screen, keyboard) = rest.split_at(Self::SCREEN_MEMORY_MAP_SIZE);
+        self.words.copy_from_slice(ram);
+        self.screen_mut().copy_from_slice(screen);
+        self.set_keyboard_reg(keyboard[0]);
     }
 
     pub fn is_screen_address(address: usize) -> bool {
-        address >= Self::SCREEN_MEMORY_MAP_OFFSET && address < (Self::SCREEN_MEMORY_MAP_OFFSET + Self::SCREEN_MEMORY_MAP_SIZE)
+        (Self::SCREEN_MEMORY_MAP_OFFSET..Self::SCREEN_MEMORY_MAP_OFFSET + Self::SCREEN_MEMORY_MAP_SIZE).contains(&address)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,187 @@
+use std::{fs::File, io::{BufWriter, Write}, collections::HashMap, cmp::Reverse};
+
+use crate::{Result, DebugInfo, Memory, Rom};
+
+// Node of the inferred call tree, node 0 is the root (code run before the first call)
+struct CallNode {
+    parent: usize,
+    function: u16,      // ROM address of the function entry
+    count: u64,         // Instructions executed while this node was on top of the stack
+}
+
+// Active call frame
+struct Frame {
+    node: usize,
+    return_address: u16,
+}
+
+// Counts executions per ROM address and per inferred call stack
+//
+// Calls are detected from the VM calling convention: a jump to a function entry
+// happens right after `LCL = SP`, with the return address pushed 5 words below LCL.
+// A jump to the return address of an active frame returns from it.
+pub struct Profiler {
+    output_prefix: String,
+    counts: Vec<u64>,
+    nodes: Vec<CallNode>,
+    children: HashMap<(usize, u16), usize>,
+    frames: Vec<Frame>,
+    current: usize,
+}
+
+impl Profiler {
+    const REPORT_ROWS: usize = 25;
+
+    pub fn new(output_prefix: String) -> Self {
+        Self {
+            output_prefix,
+            counts: vec![0; Rom::ROM_SIZE],
+            nodes: vec![CallNode { parent: 0, function: 0, count: 0 }],
+            children: HashMap::new(),
+            frames: Vec::new(),
+            current: 0,
+        }
+    }
+
+    // Record the execution of the instruction at `pc`, `next_pc` being the address executed next
+    pub fn record(&mut self, pc: usize, next_pc: usize, memory: &Memory, debug_info: &DebugInfo) {
+        self.counts[pc] += 1;
+        self.nodes[self.current].count += 1;
+
+        if next_pc != pc + 1 {
+            self.on_jump(next_pc as u16, memory, debug_info);
+        }
+    }
+
+    fn on_jump(&mut self, target: u16, memory: &Memory, debug_info: &DebugInfo) {
+        if let Some(i) = self.frames.iter().rposition(|f| f.return_address == target) {
+            self.frames.truncate(i);
+        } else if debug_info.is_function_entry(target) {
+            let sp = memory.words[Memory::SP] as usize;
+            let lcl = memory.words[Memory::LCL] as usize;
+            if sp != lcl || lcl < 5 {
+                return;
+            }
+            let return_address = match memory.peek(lcl - 5) {
+                Ok(address) => address,
+                Err(_) => return,
+            };
+            let node = self.child(self.current, target);
+            self.frames.push(Frame { node, return_address });
+        } else {
+            return;
+        }
+
+        self.current = self.frames.last().map_or(0, |f| f.node);
+    }
+
+    fn child(&mut self, parent: usize, function: u16) -> usize {
+        if let Some(node) = self.children.get(&(parent, function)) {
+            return *node;
+        }
+        self.nodes.push(CallNode { parent, function, count: 0 });
+        self.children.insert((parent, function), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    // Functions on the path from the root to the given node, outermost first
+    fn stack_of(&self, mut node: usize) -> Vec<u16> {
+        let mut stack = Vec::new();
+        while node != 0 {
+            stack.push(self.nodes[node].function);
+            node = self.nodes[node].parent;
+        }
+        stack.reverse();
+        stack
+    }
+
+    fn function_name(function: u16, debug_info: &DebugInfo) -> String {
+        match debug_info.label_at(function) {
+            Some(name) => name.to_string(),
+            None => format!("{:04X}", function),
+        }
+    }
+
+    pub fn write_reports(&self, debug_info: &DebugInfo) -> Result<()> {
+        let report_path = format!("{}.txt", self.output_prefix);
+        let folded_path = format!("{}.folded", self.output_prefix);
+        self.write_report(&report_path, debug_info)?;
+        self.write_folded(&folded_path, debug_info)?;
+        println!("Profile written to {} and {}", report_path, folded_path);
+        Ok(())
+    }
+
+    // Folded stacks, one `root;caller;callee count` line per call path, for flamegraph tools
+    fn write_folded(&self, path: &str, debug_info: &DebugInfo) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.count == 0 {
+                continue;
+            }
+            let mut names = vec!["(start)".to_string()];
+            names.extend(self.stack_of(i).iter().map(|f| Self::function_name(*f, debug_info)));
+            writeln!(out, "{} {}", names.join(";"), node.count)?;
+        }
+        Ok(())
+    }
+
+    fn write_report(&self, path: &str, debug_info: &DebugInfo) -> Result<()> {
+        let total: u64 = self.counts.iter().sum();
+        let percent = |count: u64| if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 };
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "Executed instructions: {}", total)?;
+
+        // Self and total time per function, a function appearing several times
+        // in a recursive stack is only counted once
+        let mut functions: HashMap<u16, (u64, u64)> = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate().skip(1) {
+            let mut stack = self.stack_of(i);
+            functions.entry(node.function).or_default().0 += node.count;
+            stack.sort_unstable();
+            stack.dedup();
+            for function in stack {
+                functions.entry(function).or_default().1 += node.count;
+            }
+        }
+        let mut functions: Vec<_> = functions.into_iter().collect();
+        functions.sort_by_key(|(_, (self_count, _))| Reverse(*self_count));
+
+        writeln!(out, "\nHot functions (inferred from VM calls)")?;
+        writeln!(out, "{:>12} {:>7} {:>12} {:>7}  function", "self", "%", "total", "%")?;
+        for (function, (self_count, total_count)) in functions.iter().take(Self::REPORT_ROWS) {
+            writeln!(out, "{:>12} {:>6.2}% {:>12} {:>6.2}%  {}", self_count, percent(*self_count),
+                total_count, percent(*total_count), Self::function_name(*function, debug_info))?;
+        }
+
+        // Executions aggregated by the closest preceding label
+        let mut labels: HashMap<String, u64> = HashMap::new();
+        for (address, count) in self.counts.iter().enumerate().filter(|(_, c)| **c != 0) {
+            let label = match debug_info.locate(address as u16) {
+                Some((name, _)) => name.to_string(),
+                None => "(no label)".to_string(),
+            };
+            *labels.entry(label).or_default() += count;
+        }
+        let mut labels: Vec<_> = labels.into_iter().collect();
+        labels.sort_by_key(|(_, count)| Reverse(*count));
+
+        writeln!(out, "\nHot labels")?;
+        writeln!(out, "{:>12} {:>7}  label", "count", "%")?;
+        for (label, count) in labels.iter().take(Self::REPORT_ROWS) {
+            writeln!(out, "{:>12} {:>6.2}%  {}", count, percent(*count), label)?;
+        }
+
+        let mut addresses: Vec<_> = self.counts.iter().enumerate().filter(|(_, c)| **c != 0).collect();
+        addresses.sort_by_key(|(_, count)| Reverse(**count));
+
+        writeln!(out, "\nHot instructions")?;
+        writeln!(out, "{:>12} {:>7}  {:<7} location", "count", "%", "address")?;
+        for (address, count) in addresses.iter().take(Self::REPORT_ROWS) {
+            writeln!(out, "{:>12} {:>6.2}%  {:<7} {}", count, percent(**count),
+                format!("{:04X}", address), debug_info.describe(*address as u16))?;
+        }
+
+        Ok(())
+    }
+}
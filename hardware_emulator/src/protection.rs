@@ -0,0 +1,177 @@
+use std::str::FromStr;
+
+use crate::{Result, Error, Memory, Cpu, CpuOutput, DebugInfo, ShadowMemory, Cell, MachineInit};
+
+// Faults which stop the emulator instead of being silently executed
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrapPolicy {
+    KeyboardWrite,      // Write to the keyboard register, which only the keyboard sets
+    UninitializedRead,  // Read of a RAM word never written since start
+    StackOverflow,      // SP set past the stack limit
+    RomJump,            // PC past the end of the loaded program, by a jump or by running off its end
+}
+
+impl FromStr for TrapPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        match text {
+            "kbd-write" => Ok(Self::KeyboardWrite),
+            "uninit-read" => Ok(Self::UninitializedRead),
+            "stack-overflow" => Ok(Self::StackOverflow),
+            "rom-jump" => Ok(Self::RomJump),
+            _ => Err(format!("Invalid trap '{}', expected kbd-write, uninit-read, stack-overflow or rom-jump", text)),
+        }
+    }
+}
+
+// Checks every executed instruction against the enabled trap policies
+//
+// Accesses to addresses where nothing is mapped are checked before the instruction runs,
+// the policies after it is decoded and before its RAM write, so that a trapping write never
+// reaches memory.
+pub struct Protection {
+    policies: Vec<TrapPolicy>,
+    stack_limit: u16,       // Highest allowed SP
+    program_size: usize,    // Instructions loaded in ROM
+    shadow: ShadowMemory,   // RAM words written since start
+}
+
+impl Protection {
+    pub const DEFAULT_STACK_LIMIT: u16 = 2048;
+
+    pub fn new(policies: Vec<TrapPolicy>, stack_limit: u16, program_size: usize, init: &MachineInit) -> Self {
+        Self {
+            policies,
+            stack_limit,
+            program_size,
+            shadow: ShadowMemory::new(init),
+        }
+    }
+
+    // Count all RAM as written, when its contents are replaced as a whole
+    pub fn mark_all_written(&mut self) {
+        self.shadow.mark_all_written();
+    }
+
+    pub fn reset(&mut self, init: &MachineInit) {
+        self.shadow = ShadowMemory::new(init);
+    }
+
+    // Stop an instruction reading or writing M where nothing is mapped, which would otherwise
+    // fail with an invalid address and no PC
+    pub fn check_access(&self, pc: usize, instruction: u16, cpu: &Cpu, memory: &Memory, debug_info: &DebugInfo) -> Result<()> {
+        let address = cpu.reg_a as usize;
+        let is_c = instruction >> 15 == 1;
+        let reads_m = is_c && instruction & 0x1000 != 0;
+        let writes_m = is_c && instruction & 0b001000 != 0;
+        if (reads_m || writes_m) && !memory.is_mapped(address) {
+            let access = if reads_m { "Read of" } else { "Write to" };
+            return Err(Error::Trap(format!("{} unmapped address {:04X} at {}", access, address, debug_info.describe_pc(pc))));
+        }
+        Ok(())
+    }
+
+    pub fn check(&mut self, pc: usize, instruction: u16, cpu: &Cpu, cpu_output: &CpuOutput, debug_info: &DebugInfo) -> Result<()> {
+        let trap = |message: String| Err(Error::Trap(format!("{} at {}", message, debug_info.describe_pc(pc))));
+        let address = cpu_output.memory_address;
+
+        let mut uninitialized = None;
+        self.shadow.access(instruction, cpu_output, |cell| {
+            if let Cell::Ram(_) = cell {
+                uninitialized.get_or_insert(cell);
+            }
+        });
+        if let Some(cell) = uninitialized.filter(|_| self.is_enabled(TrapPolicy::UninitializedRead)) {
+            return trap(format!("Read of uninitialized {}", cell));
+        }
+
+        if cpu_output.write_to_ram {
+            if address == Memory::KEYBOARD_REG_OFFSET && self.is_enabled(TrapPolicy::KeyboardWrite) {
+                return trap(format!("Write of {} to the keyboard register", cpu_output.alu_out));
+            }
+            if address == Memory::SP && cpu_output.alu_out > self.stack_limit && self.is_enabled(TrapPolicy::StackOverflow) {
+                return trap(format!("Stack overflow, SP set to {} past the limit {}", cpu_output.alu_out, self.stack_limit));
+            }
+        }
+
+        if cpu.pc >= self.program_size && self.is_enabled(TrapPolicy::RomJump) {
+            let fault = if cpu_output.jump_taken { "Jump to" } else { "Ran off the end to" };
+            return trap(format!("{} {:04X}, past the {} instructions of the program", fault, cpu.pc, self.program_size));
+        }
+        Ok(())
+    }
+
+    fn is_enabled(&self, policy: TrapPolicy) -> bool {
+        self.policies.contains(&policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run each instruction of a program once, as the interpreter does
+    fn run(policies: &[TrapPolicy], program: &[u16]) -> Result<()> {
+        let init = MachineInit::bare();
+        let mut protection = Protection::new(policies.to_vec(), Protection::DEFAULT_STACK_LIMIT, program.len(), &init);
+        let (mut cpu, mut memory, debug_info) = (Cpu::new(), Memory::new(), DebugInfo::default());
+        for _ in 0..program.len() {
+            let pc = cpu.pc;
+            let instruction = program[pc];
+            protection.check_access(pc, instruction, &cpu, &memory, &debug_info)?;
+            let output = cpu.execute(instruction, &mut memory)?;
+            protection.check(pc, instruction, &cpu, &output, &debug_info)?;
+            if output.write_to_ram {
+                memory.store(output.memory_address, output.alu_out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn assert_trap(policy: TrapPolicy, program: &[u16]) {
+        assert!(matches!(run(&[policy], program), Err(Error::Trap(_))));
+        assert!(run(&[], program).is_ok());
+    }
+
+    #[test]
+    fn keyboard_write() {
+        // @KBD, M=1
+        assert_trap(TrapPolicy::KeyboardWrite, &[0x6000, 0xEFC8]);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        // @2049, D=A, @SP, M=D
+        assert_trap(TrapPolicy::StackOverflow, &[2049, 0xEC10, 0x0000, 0xE308]);
+        assert!(run(&[TrapPolicy::StackOverflow], &[2048, 0xEC10, 0x0000, 0xE308]).is_ok());
+    }
+
+    #[test]
+    fn uninitialized_read() {
+        // @100, D=M
+        assert_trap(TrapPolicy::UninitializedRead, &[100, 0xFC10]);
+        // @100, M=1, D=M
+        assert!(run(&[TrapPolicy::UninitializedRead], &[100, 0xEFC8, 0xFC10]).is_ok());
+    }
+
+    #[test]
+    fn jump_past_the_program() {
+        // @100, 0;JMP
+        assert_trap(TrapPolicy::RomJump, &[100, 0xEA87]);
+    }
+
+    #[test]
+    fn running_off_the_end_of_the_program() {
+        // @5, D=A
+        assert_trap(TrapPolicy::RomJump, &[5, 0xEC10]);
+    }
+
+    #[test]
+    fn access_where_nothing_is_mapped() {
+        // @0x6001, D=M and @0x6001, M=1, trapped whatever the policies
+        for program in [[0x6001, 0xFC10], [0x6001, 0xEFC8]] {
+            assert!(matches!(run(&[], &program), Err(Error::Trap(_))));
+        }
+    }
+}
@@ -2,12 +2,17 @@ use std::fs;
 
 use crate::{Result, Error};
 
-pub struct Rom (Box<[u16]>); // ROM memory
+// ROM memory
+#[derive(Clone)]
+pub struct Rom {
+    words: Box<[u16]>,
+    size: usize,        // Number of instructions loaded from the file
+}
 
 impl Rom {
-    const ROM_SIZE: usize = 32768;
+    pub const ROM_SIZE: usize = 32768;
 
-    pub fn from_file(path : &String) -> Result<Self> {
+    pub fn from_file(path: &str) -> Result<Self> {
         let bytes = fs::read(path)?;
         if bytes.len() > Self::ROM_SIZE*2 {
             return Err(Error::RomError("ROM too large".to_string()));
@@ -19,13 +24,32 @@ impl Rom {
         for i in 0..bytes.len() / 2 {
             data[i] = u16::from_be_bytes([bytes[i * 2], bytes[i * 2 + 1]]);
         }
-        Ok(Rom(data))
+        Ok(Rom { words: data, size: bytes.len() / 2 })
+    }
+
+    // ROM holding a program given as words, like the assembler would write them
+    pub fn from_words(program: &[u16]) -> Result<Self> {
+        if program.len() > Self::ROM_SIZE {
+            return Err(Error::RomError("ROM too large".to_string()));
+        }
+        let mut words = vec![0; Self::ROM_SIZE].into_boxed_slice();
+        words[..program.len()].copy_from_slice(program);
+        Ok(Rom { words, size: program.len() })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Every ROM word, the ones past the loaded program being 0
+    pub fn words(&self) -> &[u16] {
+        &self.words
     }
 
     pub fn fetch(&self, address: usize) -> Result<u16> {
         if address >= Self::ROM_SIZE {
             return Err(Error::InvalidROMAddress(address));
         }
-        Ok(self.0[address])
+        Ok(self.words[address])
     }
 }
\ No newline at end of file
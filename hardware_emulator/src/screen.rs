@@ -1,15 +1,181 @@
 use pixels::Pixels;
-use crate::{Result, Memory};
+use crate::{Result, Memory, DisplayOptions, Scaling, Filter};
 
+// Where an output pixel row or column samples the Hack screen
+#[derive(Clone, Copy)]
+struct Sample {
+    first: usize,       // Hack pixel
+    second: usize,      // Neighbouring Hack pixel, for smooth filtering
+    weight: f32,        // Weight of `second`
+    grid: bool,         // Last output pixel of a Hack pixel
+}
+
+// Renders the screen memory map into an RGBA frame having the size of the window
+//
+// The words drawn last are kept, so a frame only redraws the rows whose words changed,
+// whichever way the memory was written (CPU, snapshot restore, direct Memory::store).
+// Scaling to the window is done here rather than by the GPU, so that every scaling mode
+// and filter is available. It needs no window, the benchmarks drive it on its own.
+pub struct Rasterizer {
+    pub options: DisplayOptions,
+    drawn: Vec<u16>,        // Screen memory map as of the last drawn frame
+    dirty_rows: Vec<bool>,
+    full_redraw: bool,      // The frame does not match `drawn`, e.g. after a resize
+    width: usize,
+    height: usize,
+    columns: Vec<Option<Sample>>,   // Per output column, None outside of the screen area
+    rows: Vec<Option<Sample>>,      // Per output row
+}
+
+impl Rasterizer {
+    const WORDS_PER_ROW: usize = Screen::WIDTH / 16;
+    const BORDER_COLOR: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+    const GRID_SHADE: f32 = 0.7;
+    const GRID_MIN_SCALE: f32 = 3.0;    // Below this, the grid would hide the picture
+
+    pub fn new(options: DisplayOptions, width: u32, height: u32) -> Self {
+        let mut rasterizer = Self {
+            options,
+            drawn: vec![0; Memory::SCREEN_MEMORY_MAP_SIZE],
+            dirty_rows: vec![false; Screen::HEIGHT],
+            full_redraw: true,
+            width: 0,
+            height: 0,
+            columns: Vec::new(),
+            rows: Vec::new(),
+        };
+        rasterizer.resize(width, height);
+        rasterizer
+    }
+
+    // Size of the frame in pixels, 4 bytes each
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width as usize;
+        self.height = height as usize;
+        self.layout();
+    }
+
+    pub fn set_options(&mut self, options: DisplayOptions) {
+        self.options = options;
+        self.layout();
+    }
+
+    // Compute where every output column and row samples the Hack screen
+    fn layout(&mut self) {
+        let scale_x = self.width as f32 / Screen::WIDTH as f32;
+        let scale_y = self.height as f32 / Screen::HEIGHT as f32;
+        let scale = match self.options.scaling {
+            Scaling::Integer => scale_x.min(scale_y).floor().max(1.0),
+            Scaling::Fit => scale_x.min(scale_y),
+        };
+        let grid = self.options.grid && scale >= Self::GRID_MIN_SCALE;
+
+        self.columns = Self::samples(self.width, Screen::WIDTH, scale, self.options.filter, grid);
+        self.rows = Self::samples(self.height, Screen::HEIGHT, scale, self.options.filter, grid);
+        self.full_redraw = true;
+    }
+
+    fn samples(output_size: usize, size: usize, scale: f32, filter: Filter, grid: bool) -> Vec<Option<Sample>> {
+        let scaled_size = (size as f32 * scale).round() as usize;
+        let start = output_size.saturating_sub(scaled_size) / 2;
+
+        (0..output_size).map(|output| {
+            let position = output.checked_sub(start).filter(|position| *position < scaled_size)?;
+            let first = ((position as f32 / scale) as usize).min(size - 1);
+            let grid = grid && ((position + 1) as f32 / scale) as usize != first;
+            let sample = match filter {
+                Filter::Nearest => Sample { first, second: first, weight: 0.0, grid },
+                Filter::Smooth => {
+                    let center = ((position as f32 + 0.5) / scale - 0.5).clamp(0.0, (size - 1) as f32);
+                    let first = center.floor() as usize;
+                    Sample { first, second: (first + 1).min(size - 1), weight: center.fract(), grid }
+                }
+            };
+            Some(sample)
+        }).collect()
+    }
+
+    // Bring the frame up to date with the screen memory map, returns the number of dirty Hack rows
+    pub fn update(&mut self, memory: &Memory, frame: &mut [u8]) -> usize {
+        let screen_map = memory.screen();
+
+        let mut dirty_count = 0;
+        let rows = screen_map.chunks_exact(Self::WORDS_PER_ROW).zip(self.drawn.chunks_exact_mut(Self::WORDS_PER_ROW));
+        for ((words, drawn_words), dirty) in rows.zip(self.dirty_rows.iter_mut()) {
+            *dirty = words != drawn_words;
+            if *dirty {
+                drawn_words.copy_from_slice(words);
+                dirty_count += 1;
+            }
+        }
+
+        if dirty_count > 0 || self.full_redraw {
+            self.draw(frame);
+        }
+        self.full_redraw = false;
+        dirty_count
+    }
+
+    // Redraw the output rows sampling a dirty Hack row
+    fn draw(&self, frame: &mut [u8]) {
+        let palette = self.options.palette;
+
+        for (output_row, pixels) in frame.chunks_exact_mut(self.width * 4).enumerate() {
+            let row = match self.rows[output_row] {
+                Some(row) => row,
+                None => {
+                    if self.full_redraw {
+                        pixels.chunks_exact_mut(4).for_each(|pixel| pixel.copy_from_slice(&Self::BORDER_COLOR));
+                    }
+                    continue;
+                }
+            };
+            if !self.full_redraw && !self.dirty_rows[row.first] && !self.dirty_rows[row.second] {
+                continue;
+            }
+
+            for (pixel, column) in pixels.chunks_exact_mut(4).zip(self.columns.iter()) {
+                let column = match column {
+                    Some(column) => column,
+                    None => {
+                        pixel.copy_from_slice(&Self::BORDER_COLOR);
+                        continue;
+                    }
+                };
+
+                let bit = |x: usize, y: usize| {
+                    let word = self.drawn[y * Self::WORDS_PER_ROW + x / 16];
+                    ((word >> (x % 16)) & 1) as f32
+                };
+                let top = bit(column.first, row.first) * (1.0 - column.weight) + bit(column.second, row.first) * column.weight;
+                let bottom = bit(column.first, row.second) * (1.0 - column.weight) + bit(column.second, row.second) * column.weight;
+                let intensity = top * (1.0 - row.weight) + bottom * row.weight;
+                let shade = if column.grid || row.grid { Self::GRID_SHADE } else { 1.0 };
+
+                for (value, (on, off)) in pixel.iter_mut().zip(palette.on.iter().zip(palette.off.iter())) {
+                    let (on, off) = (*on as f32, *off as f32);
+                    *value = ((off + (on - off) * intensity) * shade) as u8;
+                }
+                pixel[3] = 0xFF;
+            }
+        }
+    }
+}
+
+// Window display, the rasterizer drawing into the pixel buffer
 pub struct Screen {
     pub pixels: Pixels,
+    rasterizer: Rasterizer,
 }
 
 impl Screen {
-    pub fn new(pixels: Pixels) -> Self {
-        Self {
-            pixels,
-        }
+    pub const WIDTH: usize = 512;
+    pub const HEIGHT: usize = 256;
+
+    pub fn new(pixels: Pixels, options: DisplayOptions, width: u32, height: u32) -> Self {
+        let mut screen = Self { pixels, rasterizer: Rasterizer::new(options, 0, 0) };
+        screen.resize(width, height);
+        screen
     }
 
     pub fn render(&self) -> Result<()> {
@@ -17,18 +183,26 @@ impl Screen {
         Ok(())
     }
 
-    pub fn write(&mut self, address: usize, value: u16) -> Result<()> {
-        let frame = self.pixels.get_frame_mut();
-
-        let frame_lower_bound = (address as usize - Memory::SCREEN_MEMORY_MAP_OFFSET) * 16 *4;
-        for i in 0..16 {
-            let color = if value & (1 << i) != 0 { 0xFF } else { 0x00 };
-            let pixel = &mut frame[frame_lower_bound + i*4..frame_lower_bound + i*4 + 4];
-            pixel[0] = color;
-            pixel[1] = color;
-            pixel[2] = color;
-            pixel[3] = 0xFF;
+    // Follow the window size, the pixel buffer matching it one to one
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
         }
-        Ok(())
+        self.pixels.resize_surface(width, height);
+        self.pixels.resize_buffer(width, height);
+        self.rasterizer.resize(width, height);
+    }
+
+    pub fn options(&self) -> DisplayOptions {
+        self.rasterizer.options
     }
-}
\ No newline at end of file
+
+    pub fn set_options(&mut self, options: DisplayOptions) {
+        self.rasterizer.set_options(options);
+    }
+
+    // Bring the pixel buffer up to date with the screen memory map, returns the number of dirty Hack rows
+    pub fn update(&mut self, memory: &Memory) -> usize {
+        self.rasterizer.update(memory, self.pixels.get_frame_mut())
+    }
+}
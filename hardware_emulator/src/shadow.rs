@@ -0,0 +1,163 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{Memory, CpuOutput, DebugInfo, MachineInit};
+
+// Storage location an instruction can read
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Cell {
+    Ram(usize),
+    D,
+    A,
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Ram(address) => write!(f, "RAM[{}]", address),
+            Cell::D => write!(f, "D"),
+            Cell::A => write!(f, "A"),
+        }
+    }
+}
+
+// Tracks which RAM words and registers have been written since start
+pub struct ShadowMemory {
+    ram: Vec<bool>,
+    d: bool,
+    a: bool,
+}
+
+impl ShadowMemory {
+    // Registers and RAM words given a value by the machine initialization count as written
+    pub fn new(init: &MachineInit) -> Self {
+        let mut ram = vec![false; Memory::SCREEN_MEMORY_MAP_OFFSET];
+        for address in init.initialized_addresses() {
+            if let Some(written) = ram.get_mut(address) {
+                *written = true;
+            }
+        }
+        let (a, d) = init.sets_registers();
+        Self { ram, d, a }
+    }
+
+    // Count everything as written, when the machine state is replaced as a whole
+    pub fn mark_all_written(&mut self) {
+        self.ram.fill(true);
+        self.d = true;
+        self.a = true;
+    }
+
+    // Give the cells the instruction reads before they were ever written, then mark the
+    // cells it writes. The screen, keyboard and devices are always initialized.
+    pub fn access(&mut self, instruction: u16, cpu_output: &CpuOutput, mut uninitialized: impl FnMut(Cell)) {
+        if instruction >> 15 == 0 {
+            self.a = true;
+            return;
+        }
+
+        // The ALU reads D unless zx is set, and A or M unless zy is set
+        let reads_d = instruction & 0x0800 == 0;
+        let reads_y = instruction & 0x0200 == 0;
+        let uses_m = instruction & 0x1000 != 0;
+        let address = cpu_output.memory_address;
+
+        if !self.d && reads_d {
+            uninitialized(Cell::D);
+        }
+        // A is read as a value, as the address of M, or as a jump target
+        if !self.a && (reads_y || uses_m || cpu_output.write_to_ram || instruction & 0b111 != 0) {
+            uninitialized(Cell::A);
+        }
+        if uses_m && reads_y && self.ram.get(address) == Some(&false) {
+            uninitialized(Cell::Ram(address));
+        }
+
+        if cpu_output.write_to_ram {
+            if let Some(written) = self.ram.get_mut(address) {
+                *written = true;
+            }
+        }
+        self.d |= instruction & 0b010000 != 0;
+        self.a |= instruction & 0b100000 != 0;
+    }
+}
+
+// Warns about reads of never written RAM words and registers, once per instruction and cell
+pub struct UninitializedReads {
+    shadow: ShadowMemory,
+    reported: HashSet<(usize, Cell)>,
+    count: u64,         // Uninitialized reads, repeated ones included
+}
+
+impl UninitializedReads {
+    pub fn new(init: &MachineInit) -> Self {
+        Self { shadow: ShadowMemory::new(init), reported: HashSet::new(), count: 0 }
+    }
+
+    pub fn mark_all_written(&mut self) {
+        self.shadow.mark_all_written();
+    }
+
+    // Start tracking again after the machine was reset, keeping the reports
+    pub fn reset(&mut self, init: &MachineInit) {
+        self.shadow = ShadowMemory::new(init);
+    }
+
+    pub fn record(&mut self, pc: usize, instruction: u16, cpu_output: &CpuOutput, debug_info: &DebugInfo) {
+        let (reported, count) = (&mut self.reported, &mut self.count);
+        self.shadow.access(instruction, cpu_output, |cell| {
+            *count += 1;
+            if reported.insert((pc, cell)) {
+                println!("Warning: read of uninitialized {} at {}", cell, debug_info.describe_pc(pc));
+            }
+        });
+    }
+
+    pub fn report(&self) {
+        println!("Uninitialized reads: {} ({} distinct instruction and cell pairs)", self.count, self.reported.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    // Cells read before any write by each instruction of a program
+    fn uninitialized(init: &MachineInit, program: &[u16]) -> Vec<Vec<Cell>> {
+        let mut shadow = ShadowMemory::new(init);
+        let (mut cpu, mut memory) = (Cpu::new(), Memory::new());
+        program.iter().map(|instruction| {
+            let output = cpu.execute(*instruction, &mut memory).unwrap();
+            let mut cells = Vec::new();
+            shadow.access(*instruction, &output, |cell| cells.push(cell));
+            cells
+        }).collect()
+    }
+
+    #[test]
+    fn d_read_before_write() {
+        // D=D+1, D=D+1
+        assert_eq!(uninitialized(&MachineInit::bare(), &[0xE7D0, 0xE7D0]), [vec![Cell::D], vec![]]);
+    }
+
+    #[test]
+    fn a_read_before_write() {
+        // D=A, @5, D=A
+        assert_eq!(uninitialized(&MachineInit::bare(), &[0xEC10, 5, 0xEC10]), [vec![Cell::A], vec![], vec![]]);
+    }
+
+    #[test]
+    fn m_read_before_write() {
+        // @100, D=M, M=1, D=M
+        assert_eq!(uninitialized(&MachineInit::bare(), &[100, 0xFC10, 0xEFC8, 0xFC10]), [vec![], vec![Cell::Ram(100)], vec![], vec![]]);
+    }
+
+    #[test]
+    fn initialized_words_and_screen_count_as_written() {
+        // @SP, D=M, @SCREEN, D=M
+        let program = [0, 0xFC10, 0x4000, 0xFC10];
+        assert_eq!(uninitialized(&MachineInit::vm_bootstrap(), &program), [vec![], vec![], vec![], vec![]]);
+        assert_eq!(uninitialized(&MachineInit::bare(), &program), [vec![], vec![Cell::Ram(0)], vec![], vec![]]);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fs, io::Write};
+
+use crate::{Result, Error, Cpu, Memory};
+
+// Snapshot of the whole machine state: CPU registers and data memory
+//
+// File layout (all values big-endian):
+//   magic    8 bytes  "HACKSNAP"
+//   version  u16
+//   reg_a    u16
+//   reg_d    u16
+//   pc       u32
+//   length   u32      number of memory words that follow
+//   memory   length * u16
+#[derive(PartialEq, Eq)]
+pub struct Snapshot {
+    pub reg_a: u16,
+    pub reg_d: u16,
+    pub pc: usize,
+    pub memory: Vec<u16>,
+}
+
+impl Snapshot {
+    const MAGIC: &'static [u8; 8] = b"HACKSNAP";
+    const VERSION: u16 = 1;
+    const HEADER_SIZE: usize = 8 + 2 + 2 + 2 + 4 + 4;
+
+    pub fn capture(cpu: &Cpu, memory: &Memory) -> Self {
+        Self {
+            reg_a: cpu.reg_a,
+            reg_d: cpu.reg_d,
+            pc: cpu.pc,
+            memory: memory.data_words(),
+        }
+    }
+
+    pub fn restore(&self, cpu: &mut Cpu, memory: &mut Memory) {
+        cpu.reg_a = self.reg_a;
+        cpu.reg_d = self.reg_d;
+        cpu.pc = self.pc;
+        memory.set_data_words(&self.memory);
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut bytes = Vec::with_capacity(Self::HEADER_SIZE + self.memory.len() * 2);
+        bytes.write_all(Self::MAGIC)?;
+        bytes.write_all(&Self::VERSION.to_be_bytes())?;
+        bytes.write_all(&self.reg_a.to_be_bytes())?;
+        bytes.write_all(&self.reg_d.to_be_bytes())?;
+        bytes.write_all(&(self.pc as u32).to_be_bytes())?;
+        bytes.write_all(&(self.memory.len() as u32).to_be_bytes())?;
+        for word in self.memory.iter() {
+            bytes.write_all(&word.to_be_bytes())?;
+        }
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < Self::HEADER_SIZE || &bytes[0..8] != Self::MAGIC {
+            return Err(Error::StateError(format!("{} is not a Hack snapshot", path)));
+        }
+
+        let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        let version = u16_at(8);
+        if version != Self::VERSION {
+            return Err(Error::StateError(format!("Unsupported snapshot version {}, expected {}", version, Self::VERSION)));
+        }
+
+        let reg_a = u16_at(10);
+        let reg_d = u16_at(12);
+        let pc = u32_at(14) as usize;
+        let length = u32_at(18) as usize;
+
+        if length != Memory::TOTAL_SIZE {
+            return Err(Error::StateError(format!("Snapshot holds {} memory words, expected {}", length, Memory::TOTAL_SIZE)));
+        }
+        if bytes.len() != Self::HEADER_SIZE + length * 2 {
+            return Err(Error::StateError("Truncated snapshot".to_string()));
+        }
+
+        let memory = (0..length)
+            .map(|i| u16_at(Self::HEADER_SIZE + i * 2))
+            .collect();
+
+        Ok(Self { reg_a, reg_d, pc, memory })
+    }
+}
@@ -0,0 +1,182 @@
+use std::{io::{self, Write}, collections::HashMap, cmp::Reverse};
+
+use crate::{Result, Memory, CpuOutput, Bus};
+
+// Memory region of the VM mapping, with its first address
+const REGIONS: [(&str, usize); 7] = [
+    ("R0-R15", 0),
+    ("statics", 16),
+    ("stack", 256),
+    ("heap", 2048),
+    ("screen", Memory::SCREEN_MEMORY_MAP_OFFSET),
+    ("keyboard", Memory::KEYBOARD_REG_OFFSET),
+    ("devices", Memory::TOTAL_SIZE),
+];
+
+// Assembly mnemonics of the computations, indexed by the a bit and the 6 c bits
+const COMPUTATIONS: [(u16, &str); 28] = [
+    (0b0101010, "0"), (0b0111111, "1"), (0b0111010, "-1"), (0b0001100, "D"), (0b0110000, "A"),
+    (0b0001101, "!D"), (0b0110001, "!A"), (0b0001111, "-D"), (0b0110011, "-A"), (0b0011111, "D+1"),
+    (0b0110111, "A+1"), (0b0001110, "D-1"), (0b0110010, "A-1"), (0b0000010, "D+A"), (0b0010011, "D-A"),
+    (0b0000111, "A-D"), (0b0000000, "D&A"), (0b0010101, "D|A"), (0b1110000, "M"), (0b1110001, "!M"),
+    (0b1110011, "-M"), (0b1110111, "M+1"), (0b1110010, "M-1"), (0b1000010, "D+M"), (0b1010011, "D-M"),
+    (0b1000111, "M-D"), (0b1000000, "D&M"), (0b1010101, "D|M"),
+];
+const DESTINATIONS: [&str; 8] = ["null", "M", "D", "MD", "A", "AM", "AD", "AMD"];
+const JUMPS: [&str; 8] = ["null", "JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+
+// Instruction mix, branch behaviour and memory traffic of a run, for tuning code generators
+#[derive(Default)]
+pub struct Statistics {
+    a_instructions: u64,
+    c_instructions: u64,
+    computations: HashMap<u16, u64>,
+    destinations: [u64; 8],
+    jumps: [u64; 8],
+    conditional_jumps: u64,
+    taken_jumps: u64,           // Conditional jumps taken
+    reads: [u64; REGIONS.len()],
+    writes: [u64; REGIONS.len()],
+    peak_sp: Option<u16>,       // Highest value written to SP
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, instruction: u16, cpu_output: &CpuOutput) {
+        if instruction >> 15 == 0 {
+            self.a_instructions += 1;
+            return;
+        }
+        self.c_instructions += 1;
+
+        let jump = (instruction & 0b111) as usize;
+        *self.computations.entry((instruction >> 6) & 0b1111111).or_insert(0) += 1;
+        self.destinations[((instruction >> 3) & 0b111) as usize] += 1;
+        self.jumps[jump] += 1;
+        if jump != 0b000 && jump != 0b111 {
+            self.conditional_jumps += 1;
+            if cpu_output.jump_taken {
+                self.taken_jumps += 1;
+            }
+        }
+
+        let region = REGIONS.iter().rposition(|(_, start)| cpu_output.memory_address >= *start).unwrap();
+        if instruction & 0x1000 != 0 {
+            self.reads[region] += 1;
+        }
+        if cpu_output.write_to_ram {
+            self.writes[region] += 1;
+            if cpu_output.memory_address == Memory::SP {
+                self.peak_sp = self.peak_sp.max(Some(cpu_output.alu_out));
+            }
+        }
+    }
+
+    pub fn report(&self) -> Result<()> {
+        self.write_report(&mut io::stdout().lock())
+    }
+
+    fn write_report(&self, out: &mut impl Write) -> Result<()> {
+        let total = self.a_instructions + self.c_instructions;
+        let percent = |count: u64, total: u64| if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 };
+
+        writeln!(out, "Instruction statistics ({} instructions)", total)?;
+        writeln!(out, "  {:<10} {:>12} {:>6.1}%", "A", self.a_instructions, percent(self.a_instructions, total))?;
+        writeln!(out, "  {:<10} {:>12} {:>6.1}%", "C", self.c_instructions, percent(self.c_instructions, total))?;
+
+        let mut computations: Vec<(String, u64)> = self.computations.iter()
+            .map(|(comp, count)| (Self::computation_name(*comp), *count))
+            .collect();
+        computations.sort_by_key(|(name, count)| (Reverse(*count), name.clone()));
+        writeln!(out, "\nComputations, of the C instructions")?;
+        for (name, count) in computations.iter() {
+            writeln!(out, "  {:<10} {:>12} {:>6.1}%", name, count, percent(*count, self.c_instructions))?;
+        }
+
+        for (title, names, counts) in [("Destinations", DESTINATIONS, self.destinations), ("Jumps", JUMPS, self.jumps)] {
+            writeln!(out, "\n{}, of the C instructions", title)?;
+            for (name, count) in names.iter().zip(counts.iter()).filter(|(_, count)| **count != 0) {
+                writeln!(out, "  {:<10} {:>12} {:>6.1}%", name, count, percent(*count, self.c_instructions))?;
+            }
+        }
+
+        writeln!(out, "\nConditional jumps: {}, taken {} ({:.1}%)", self.conditional_jumps, self.taken_jumps,
+            percent(self.taken_jumps, self.conditional_jumps))?;
+
+        writeln!(out, "\n{:<24} {:>12} {:>12}", "Memory accesses", "reads", "writes")?;
+        for (i, (name, start)) in REGIONS.iter().enumerate() {
+            let end = REGIONS.get(i + 1).map_or(Bus::END, |(_, next)| *next) - 1;
+            let range = if *start == end { format!("{}", start) } else { format!("{}-{}", start, end) };
+            writeln!(out, "  {:<8} {:<13} {:>12} {:>12}", name, range, self.reads[i], self.writes[i])?;
+        }
+
+        match self.peak_sp {
+            Some(sp) => writeln!(out, "\nPeak SP: {}", sp)?,
+            None => writeln!(out, "\nPeak SP: never written")?,
+        }
+        Ok(())
+    }
+
+    fn computation_name(comp: u16) -> String {
+        match COMPUTATIONS.iter().find(|(code, _)| *code == comp) {
+            Some((_, name)) => name.to_string(),
+            None => format!("{:07b}", comp),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Emulator, Rom};
+
+    fn run(program: &[u16], cycles: u64) -> Statistics {
+        let mut emulator = Emulator::new(Rom::from_words(program).unwrap(), None, String::new());
+        emulator.statistics = Some(Statistics::new());
+        emulator.max_cycles = Some(cycles);
+        emulator.run_to_end().unwrap();
+        emulator.statistics.unwrap()
+    }
+
+    #[test]
+    fn instructions_jumps_and_memory_regions() {
+        let program = [
+            300, 0xEC10, 0, 0xE308,     // @300, D=A, @SP, M=D
+            0x4000, 0xEE88, 0xFC10,     // @SCREEN, M=-1, D=M
+            100, 0xE302,                // @100, D;JEQ, not taken
+            2000, 0xE308,               // @2000, M=D
+            13, 0xE304,                 // @13, D;JLT, taken
+        ];
+        let statistics = run(&program, 13);
+
+        assert_eq!((statistics.a_instructions, statistics.c_instructions), (6, 7));
+        assert_eq!(statistics.computations[&0b0001100], 4);    // D
+        assert_eq!(statistics.computations[&0b0110000], 1);    // A
+        assert_eq!(statistics.computations[&0b0111010], 1);    // -1
+        assert_eq!(statistics.computations[&0b1110000], 1);    // M
+        assert_eq!(statistics.destinations, [2, 3, 2, 0, 0, 0, 0, 0]);
+        assert_eq!(statistics.jumps, [5, 0, 1, 0, 1, 0, 0, 0]);
+        assert_eq!((statistics.conditional_jumps, statistics.taken_jumps), (2, 1));
+        assert_eq!(statistics.reads, [0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(statistics.writes, [1, 0, 1, 0, 1, 0, 0]);
+        assert_eq!(statistics.peak_sp, Some(300));
+    }
+
+    #[test]
+    fn report_lists_the_counts() {
+        // @SP, M=M+1, @0, 0;JMP
+        let statistics = run(&[0, 0xFDC8, 0, 0xEA87], 400);
+        let mut out = Vec::new();
+        statistics.write_report(&mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+
+        assert!(report.starts_with("Instruction statistics (400 instructions)"));
+        assert!(report.contains("  M+1                 100   50.0%"));
+        assert!(report.contains("  JMP                 100   50.0%"));
+        assert!(report.contains("  R0-R15   0-15                   100          100"));
+        assert!(report.contains("Peak SP: 100"));
+    }
+}
@@ -0,0 +1,430 @@
+use std::{fs, fmt::Write as _, process::Command, env, path::PathBuf, sync::atomic::{AtomicUsize, Ordering}};
+
+use crate::{Result, Error, Emulator, Rom, Memory, Snapshot};
+
+// Translates a ROM into a standalone Rust program behaving like Cpu::execute
+//
+// Each basic block becomes a function, and every ROM address has an entry in a dispatch
+// table used for computed jumps through A. Blocks start at address 0, at every address
+// loaded by an A instruction and after every jump. Addresses which are not the start of
+// a block are run one instruction at a time by a small interpreter embedded in the
+// generated program, which is also used near the end of the cycle budget so that runs
+// stop on the exact same instruction as the emulator.
+//
+// The generated program takes `CYCLES [OUTPUT_STATE] [INPUT_STATE]` arguments, and reads
+// and writes the emulator's snapshot format.
+pub struct Translator<'a> {
+    emulator: &'a Emulator,
+    program: Vec<u16>,
+    leaders: Vec<bool>,
+}
+
+impl<'a> Translator<'a> {
+    pub fn new(emulator: &'a Emulator) -> Self {
+        // Everything after the last non-null word is `@0`, run by the embedded interpreter
+        let words = emulator.rom.words();
+        let size = words.iter().rposition(|word| *word != 0).map_or(0, |last| last + 1);
+        let program = words[..size].to_vec();
+
+        let mut leaders = vec![false; size];
+        if size > 0 {
+            leaders[0] = true;
+        }
+        for (address, word) in program.iter().enumerate() {
+            if word >> 15 == 0 {
+                if let Some(leader) = leaders.get_mut(*word as usize) {
+                    *leader = true;
+                }
+            } else if word & 0b111 != 0 {
+                if let Some(leader) = leaders.get_mut(address + 1) {
+                    *leader = true;
+                }
+            }
+        }
+
+        Self { emulator, program, leaders }
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        if self.emulator.memory.bus.has_extra_devices() {
+            return Err(Error::TranslationError("Devices are not supported by the translated program".to_string()));
+        }
+        fs::write(path, self.generate())?;
+        println!("Translated {} instructions to {}", self.program.len(), path);
+        Ok(())
+    }
+
+    fn generate(&self) -> String {
+        let mut out = String::new();
+        let cpu = &self.emulator.cpu;
+
+        writeln!(out, "// Generated by hardware_emulator --translate, do not edit").unwrap();
+        writeln!(out, "#![allow(unused_variables, clippy::all)]\n").unwrap();
+        writeln!(out, "const MEMORY_SIZE: usize = {};", Memory::TOTAL_SIZE).unwrap();
+        writeln!(out, "const ROM_SIZE: usize = {};", Rom::ROM_SIZE).unwrap();
+        writeln!(out, "const INITIAL_REGISTERS: (u16, u16, usize) = ({}, {}, {});", cpu.reg_a, cpu.reg_d, cpu.pc).unwrap();
+
+        let initial_memory: Vec<String> = self.emulator.memory.data_words().iter().enumerate()
+            .filter(|(_, value)| **value != 0)
+            .map(|(address, value)| format!("({}, {})", address, value))
+            .collect();
+        writeln!(out, "const INITIAL_MEMORY: &[(usize, u16)] = &[{}];", initial_memory.join(", ")).unwrap();
+
+        let rom: Vec<String> = self.program.iter().map(|word| word.to_string()).collect();
+        writeln!(out, "static ROM: [u16; {}] = [{}];\n", rom.len(), rom.join(", ")).unwrap();
+
+        out.push_str(RUNTIME);
+
+        let table: Vec<String> = (0..self.program.len())
+            .map(|address| match self.block_size(address) {
+                Some(_) => format!("b{}", address),
+                None => "step".to_string(),
+            })
+            .collect();
+        writeln!(out, "\nstatic BLOCKS: [Block; {}] = [{}];", table.len(), table.join(", ")).unwrap();
+
+        for address in 0..self.program.len() {
+            if let Some(size) = self.block_size(address) {
+                self.generate_block(&mut out, address, size);
+            }
+        }
+        out
+    }
+
+    fn is_valid(instruction: u16) -> bool {
+        instruction >> 15 == 0 || (instruction >> 13 == 0b111 && Self::alu_expression((instruction >> 6) & 0b111111).is_some())
+    }
+
+    // Number of instructions in the block starting at the address, None if it does not start a block
+    fn block_size(&self, start: usize) -> Option<usize> {
+        if !self.leaders[start] || !Self::is_valid(self.program[start]) {
+            return None;
+        }
+        let mut end = start;
+        loop {
+            let word = self.program[end];
+            let is_jump = word >> 15 == 1 && word & 0b111 != 0;
+            let next = end + 1;
+            if is_jump || next >= self.program.len() || self.leaders[next] || !Self::is_valid(self.program[next]) {
+                return Some(next - start);
+            }
+            end = next;
+        }
+    }
+
+    fn generate_block(&self, out: &mut String, start: usize, size: usize) {
+        writeln!(out, "\nfn b{}(m: &mut [u16], st: &mut State) -> Result<(), String> {{", start).unwrap();
+        writeln!(out, "    if st.max - st.cycles < {} {{ return step(m, st); }}", size).unwrap();
+
+        let mut jumped = false;
+        for (done, address) in (start..start + size).enumerate() {
+            let word = self.program[address];
+            if word >> 15 == 0 {
+                writeln!(out, "    st.a = {};", word).unwrap();
+                continue;
+            }
+
+            let dest = (word >> 3) & 0b111;
+            let jump = word & 0b111;
+            let expression = Self::alu_expression((word >> 6) & 0b111111).unwrap();
+
+            writeln!(out, "    {{").unwrap();
+            if (word >> 12) & 0b1 != 0 {
+                writeln!(out, "        let y = rd(m, st, {}, {})?;", address, done).unwrap();
+            } else if expression.contains('y') {
+                writeln!(out, "        let y = st.a;").unwrap();
+            }
+            writeln!(out, "        let o: u16 = {};", expression).unwrap();
+            if dest & 0b001 != 0 {
+                writeln!(out, "        let t = st.a;").unwrap();
+            }
+            if dest & 0b010 != 0 {
+                writeln!(out, "        st.d = o;").unwrap();
+            }
+            if dest & 0b100 != 0 {
+                writeln!(out, "        st.a = o;").unwrap();
+            }
+            if jump == 0b111 {
+                writeln!(out, "        st.pc = st.a as usize;").unwrap();
+                jumped = true;
+            } else if jump != 0 {
+                writeln!(out, "        st.pc = if {} & cond(o) != 0 {{ st.a as usize }} else {{ {} }};", jump, address + 1).unwrap();
+                jumped = true;
+            }
+            if dest & 0b001 != 0 {
+                let next = if jumped { "KEEP_PC".to_string() } else { (address + 1).to_string() };
+                writeln!(out, "        wr(m, st, t, o, {}, {})?;", next, done).unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+
+        if !jumped {
+            writeln!(out, "    st.pc = {};", start + size).unwrap();
+        }
+        writeln!(out, "    st.cycles += {};", size).unwrap();
+        writeln!(out, "    Ok(())").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    // Same computations as Cpu::alu_compute, with `st.d` and `y` operands
+    fn alu_expression(comp: u16) -> Option<&'static str> {
+        Some(match comp {
+            0b101010 => "0",
+            0b111111 => "1",
+            0b111010 => "0xFFFF",
+            0b001100 => "st.d",
+            0b110000 => "y",
+            0b001101 => "!st.d",
+            0b110001 => "!y",
+            0b001111 => "st.d.wrapping_neg()",
+            0b110011 => "y.wrapping_neg()",
+            0b011111 => "st.d.wrapping_add(1)",
+            0b110111 => "y.wrapping_add(1)",
+            0b001110 => "st.d.wrapping_sub(1)",
+            0b110010 => "y.wrapping_sub(1)",
+            0b000010 => "st.d.wrapping_add(y)",
+            0b010011 => "st.d.wrapping_sub(y)",
+            0b000111 => "y.wrapping_sub(st.d)",
+            0b000000 => "st.d & y",
+            0b010101 => "st.d | y",
+            _ => return None,
+        })
+    }
+}
+
+// Translate the program, compile it with rustc, and check that it ends in the same state as the interpreter
+pub fn verify_translation(emulator: Emulator, cycles: u64) -> Result<()> {
+    let work_dir = WorkDir::create(env::temp_dir().join(format!("hack_translation_{}_{}", std::process::id(),
+        WORK_DIRS.fetch_add(1, Ordering::Relaxed))))?;
+    let path = |name: &str| work_dir.0.join(name).to_string_lossy().into_owned();
+
+    Translator::new(&emulator).write(&path("translated.rs"))?;
+
+    let status = Command::new("rustc").args(["-O", "-o", &path("translated"), &path("translated.rs")]).status()?;
+    if !status.success() {
+        return Err(Error::TranslationError("rustc failed to compile the translated program".to_string()));
+    }
+
+    let mut interpreter = emulator;
+    interpreter.max_cycles = Some(cycles);
+    let interpreter_result = interpreter.run_to_end();
+    let expected = Snapshot::capture(&interpreter.cpu, &interpreter.memory);
+
+    let output = Command::new(path("translated")).args([&cycles.to_string(), &path("translated.state")]).output()?;
+    let translated = Snapshot::load(&path("translated.state"))?;
+
+    println!("Interpreter: {} instructions{}", interpreter.cycles,
+        interpreter_result.err().map_or(String::new(), |e| format!(", stopped on: {}", e)));
+    print!("Translated:  {}", String::from_utf8_lossy(&output.stdout));
+
+    if translated != expected {
+        return Err(Error::TranslationError("Final states differ".to_string()));
+    }
+    println!("Final states match");
+    Ok(())
+}
+
+// Temporary build directory, removed however the verification ends
+struct WorkDir(PathBuf);
+
+// Verifications started by this process, keeping the directories of concurrent ones apart
+static WORK_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+impl WorkDir {
+    fn create(path: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Support code of the generated program
+const RUNTIME: &str = r#"type Block = fn(&mut [u16], &mut State) -> Result<(), String>;
+
+const KEEP_PC: usize = usize::MAX;
+
+struct State {
+    a: u16,
+    d: u16,
+    pc: usize,
+    cycles: u64,
+    max: u64,
+}
+
+fn cond(o: u16) -> u16 {
+    if o == 0 { 0b010 } else if o & 0x8000 != 0 { 0b100 } else { 0b001 }
+}
+
+// Read M for the instruction at `pc`, `done` instructions of the block being already executed
+#[inline(always)]
+fn rd(m: &[u16], st: &mut State, pc: usize, done: u64) -> Result<u16, String> {
+    match m.get(st.a as usize) {
+        Some(value) => Ok(*value),
+        None => {
+            st.pc = pc;
+            st.cycles += done;
+            Err(format!("Invalid Memory Address: {:04X}", st.a))
+        }
+    }
+}
+
+#[inline(always)]
+fn wr(m: &mut [u16], st: &mut State, address: u16, value: u16, next_pc: usize, done: u64) -> Result<(), String> {
+    match m.get_mut(address as usize) {
+        Some(word) => {
+            *word = value;
+            Ok(())
+        }
+        None => {
+            if next_pc != KEEP_PC {
+                st.pc = next_pc;
+            }
+            st.cycles += done;
+            Err(format!("Invalid Memory Address: {:04X}", address))
+        }
+    }
+}
+
+// Execute a single instruction, like Cpu::execute
+fn step(m: &mut [u16], st: &mut State) -> Result<(), String> {
+    let pc = st.pc;
+    if pc >= ROM_SIZE {
+        return Err(format!("Invalid ROM Address: {:04X}", pc));
+    }
+    let instruction = if pc < ROM.len() { ROM[pc] } else { 0 };
+
+    if instruction >> 15 == 0 {
+        st.a = instruction & 0x7FFF;
+        st.pc += 1;
+        st.cycles += 1;
+        return Ok(());
+    }
+    if instruction >> 13 != 0b111 {
+        return Err(format!("Invalid C Instruction Padding, should be 111, at PC: {:04X}", pc));
+    }
+
+    let y = if (instruction >> 12) & 1 == 0 { st.a } else { rd(m, st, pc, 0)? };
+    let d = st.d;
+    let o = match (instruction >> 6) & 0b111111 {
+        0b101010 => 0,
+        0b111111 => 1,
+        0b111010 => 0xFFFF,
+        0b001100 => d,
+        0b110000 => y,
+        0b001101 => !d,
+        0b110001 => !y,
+        0b001111 => d.wrapping_neg(),
+        0b110011 => y.wrapping_neg(),
+        0b011111 => d.wrapping_add(1),
+        0b110111 => y.wrapping_add(1),
+        0b001110 => d.wrapping_sub(1),
+        0b110010 => y.wrapping_sub(1),
+        0b000010 => d.wrapping_add(y),
+        0b010011 => d.wrapping_sub(y),
+        0b000111 => y.wrapping_sub(d),
+        0b000000 => d & y,
+        0b010101 => d | y,
+        _ => return Err(format!("Invalid C Instruction Computation, at PC: {:04X}", pc.wrapping_sub(1))),
+    };
+
+    let dest = (instruction >> 3) & 0b111;
+    let address = st.a;
+    if dest & 0b010 != 0 {
+        st.d = o;
+    }
+    if dest & 0b100 != 0 {
+        st.a = o;
+    }
+    st.pc = if instruction & 0b111 & cond(o) != 0 { st.a as usize } else { pc + 1 };
+    if dest & 0b001 != 0 {
+        wr(m, st, address, o, KEEP_PC, 0)?;
+    }
+    st.cycles += 1;
+    Ok(())
+}
+
+fn run(m: &mut [u16], st: &mut State) -> Result<(), String> {
+    while st.cycles < st.max {
+        match BLOCKS.get(st.pc) {
+            Some(block) => block(m, st)?,
+            None => step(m, st)?,
+        }
+    }
+    Ok(())
+}
+
+// Snapshot in the emulator's format
+fn save_state(path: &str, m: &[u16], st: &State) {
+    let mut bytes = b"HACKSNAP".to_vec();
+    bytes.extend_from_slice(&1u16.to_be_bytes());
+    bytes.extend_from_slice(&st.a.to_be_bytes());
+    bytes.extend_from_slice(&st.d.to_be_bytes());
+    bytes.extend_from_slice(&(st.pc as u32).to_be_bytes());
+    bytes.extend_from_slice(&(m.len() as u32).to_be_bytes());
+    for word in m {
+        bytes.extend_from_slice(&word.to_be_bytes());
+    }
+    std::fs::write(path, bytes).expect("cannot write the state file");
+}
+
+fn load_state(path: &str, m: &mut [u16], st: &mut State) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    if bytes.len() < 22 || &bytes[0..8] != b"HACKSNAP" {
+        return Err(format!("{} is not a Hack snapshot", path));
+    }
+    let version = u16::from_be_bytes([bytes[8], bytes[9]]);
+    if version != 1 {
+        return Err(format!("Unsupported snapshot version {}, expected 1", version));
+    }
+    let length = u32::from_be_bytes([bytes[18], bytes[19], bytes[20], bytes[21]]) as usize;
+    if length != m.len() {
+        return Err(format!("Snapshot holds {} memory words, expected {}", length, m.len()));
+    }
+    if bytes.len() != 22 + length * 2 {
+        return Err("Truncated snapshot".to_string());
+    }
+    st.a = u16::from_be_bytes([bytes[10], bytes[11]]);
+    st.d = u16::from_be_bytes([bytes[12], bytes[13]]);
+    st.pc = u32::from_be_bytes([bytes[14], bytes[15], bytes[16], bytes[17]]) as usize;
+    for (i, word) in m.iter_mut().enumerate() {
+        *word = u16::from_be_bytes([bytes[22 + i * 2], bytes[23 + i * 2]]);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        println!("Usage: {} CYCLES [OUTPUT_STATE] [INPUT_STATE]", args[0]);
+        std::process::exit(2);
+    }
+
+    let mut m = vec![0u16; MEMORY_SIZE];
+    let (a, d, pc) = INITIAL_REGISTERS;
+    let mut st = State { a, d, pc, cycles: 0, max: args[1].parse().expect("invalid cycle count") };
+    for (address, value) in INITIAL_MEMORY {
+        m[*address] = *value;
+    }
+    if let Some(path) = args.get(3) {
+        if let Err(e) = load_state(path, &mut m, &mut st) {
+            println!("State Error: {}", e);
+            std::process::exit(2);
+        }
+    }
+
+    let result = run(&mut m, &mut st);
+    println!("{} instructions", st.cycles);
+    if let Some(path) = args.get(2) {
+        save_state(path, &m, &st);
+    }
+    if let Err(e) = result {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+"#;
@@ -0,0 +1,77 @@
+use hardware_emulator::{Bus, Device, Memory, Error, Rng, Timer};
+
+// Devices attached to the bus, read and written the way the CPU does
+
+#[test]
+fn screen_and_keyboard_are_attached() {
+    let mut bus = Bus::new();
+    assert!(!bus.has_extra_devices());
+    bus.write(Memory::SCREEN_MEMORY_MAP_OFFSET + 10, 0x1234).unwrap();
+    bus.write(Memory::KEYBOARD_REG_OFFSET, 65).unwrap();
+    assert_eq!(bus.read(Memory::SCREEN_MEMORY_MAP_OFFSET + 10).unwrap(), 0x1234);
+    assert_eq!(bus.memory(Memory::SCREEN_MEMORY_MAP_OFFSET).unwrap()[10], 0x1234);
+    assert_eq!(bus.peek(Memory::KEYBOARD_REG_OFFSET).unwrap(), 65);
+}
+
+#[test]
+fn attached_device_answers_its_addresses() {
+    let mut bus = Bus::new();
+    bus.attach(0x6010, Box::new(Timer::new(1000))).unwrap();
+    assert!(bus.has_extra_devices());
+    assert!(bus.is_mapped(0x6010) && bus.is_mapped(0x6014));
+    assert!(!bus.is_mapped(0x600F) && !bus.is_mapped(0x6015));
+    bus.cycle = 42;
+    assert_eq!(bus.read(0x6010).unwrap(), 42);
+}
+
+#[test]
+fn attach_outside_of_the_bus_is_an_error() {
+    let mut bus = Bus::new();
+    assert!(matches!(bus.attach(0x3FFF, Box::new(Rng::new())), Err(Error::DeviceError(_))));
+    assert!(matches!(bus.attach(Bus::END - 2, Box::new(Timer::new(1000))), Err(Error::DeviceError(_))));
+    bus.attach(Bus::END - 1, Box::new(Rng::new())).unwrap();
+}
+
+#[test]
+fn attach_over_another_device_is_an_error() {
+    let mut bus = Bus::new();
+    bus.attach(0x6010, Box::new(Timer::new(1000))).unwrap();
+    for address in [0x5FFF, 0x6000, 0x600C, 0x6014] {
+        assert!(matches!(bus.attach(address, Box::new(Timer::new(1000))), Err(Error::DeviceError(_))), "{:04X}", address);
+    }
+    bus.attach(0x6015, Box::new(Rng::new())).unwrap();
+    bus.attach(0x600F, Box::new(Rng::new())).unwrap();
+}
+
+#[test]
+fn unmapped_addresses_are_invalid() {
+    let mut bus = Bus::new();
+    assert!(matches!(bus.read(0x6001), Err(Error::InvalidMemoryAddress(0x6001))));
+    assert!(matches!(bus.write(0x7FFF, 1), Err(Error::InvalidMemoryAddress(0x7FFF))));
+    assert!(matches!(bus.peek(0x8000), Err(Error::InvalidMemoryAddress(0x8000))));
+}
+
+#[test]
+fn peek_and_poke_only_reach_plain_memory() {
+    let mut bus = Bus::new();
+    bus.attach(0x6010, Box::new(Rng::new())).unwrap();
+    assert!(matches!(bus.peek(0x6010), Err(Error::InvalidMemoryAddress(_))));
+    assert!(matches!(bus.poke(0x6010, 1), Err(Error::InvalidMemoryAddress(_))));
+    bus.poke(Memory::SCREEN_MEMORY_MAP_OFFSET, 7).unwrap();
+    assert_eq!(bus.read(Memory::SCREEN_MEMORY_MAP_OFFSET).unwrap(), 7);
+}
+
+#[test]
+fn rng_is_reproducible_from_its_seed() {
+    let mut first = Rng::new();
+    let mut second = Rng::new();
+    let numbers: Vec<u16> = (0..100).map(|_| first.read(0, 0)).collect();
+    assert!(numbers.iter().all(|number| *number != 0));
+    assert_eq!(numbers, (0..100).map(|_| second.read(0, 0)).collect::<Vec<u16>>());
+
+    first.write(0, 1234, 0);
+    second.write(0, 1234, 0);
+    assert_eq!(first.read(0, 0), second.read(0, 0));
+    second.write(0, 0, 0);
+    assert_eq!(second.read(0, 0), numbers[0]);
+}
@@ -0,0 +1,88 @@
+use std::{env, fs, path::PathBuf};
+
+use hardware_emulator::{Emulator, Rom, Clock, Memory, Palette, ScreenAssertion, Error, save_screenshot};
+
+// Frame recordings and screen assertions, on the screen of a program or of a memory set by the test
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hack_capture_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// First word of each frame of a raw recording, whose bytes hold the pixels leftmost first
+fn first_words(path: &PathBuf) -> Vec<u16> {
+    let frame_size = Memory::SCREEN_MEMORY_MAP_SIZE * 2;
+    let bytes = fs::read(path).unwrap();
+    assert_eq!(bytes.len() % frame_size, 0);
+    bytes.chunks_exact(frame_size)
+        .map(|frame| frame[0].reverse_bits() as u16 | (frame[1].reverse_bits() as u16) << 8)
+        .collect()
+}
+
+#[test]
+fn recording_captures_a_frame_every_clock_share() {
+    let dir = test_dir("recording");
+    let path = dir.join("frames.raw");
+
+    // SCREEN[0] += 1 every 4 instructions: (LOOP) @SCREEN, M=M+1, @LOOP, 0;JMP
+    let rom = Rom::from_words(&[0x4000, 0xFDC8, 0x0000, 0xEA87]).unwrap();
+    let mut emulator = Emulator::new(rom, None, String::new());
+    emulator.clock = Clock::new(1000);
+    emulator.record_fps = 30;
+    emulator.max_cycles = Some(1000);
+    emulator.start_recording(path.to_string_lossy().into_owned()).unwrap();
+    emulator.run_to_end().unwrap();
+    emulator.stop_recording().unwrap();
+
+    // Frame n at cycle n * 1000 / 30, after (cycle + 2) / 4 increments, frame 30 at the last cycle
+    let expected: Vec<u16> = (0..=30).map(|frame: u64| ((frame * 1000 / 30 + 2) / 4) as u16).collect();
+    assert_eq!(first_words(&path), expected);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn screen_assertion_compares_the_screen_outside_of_the_mask() {
+    let dir = test_dir("assertion");
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+    let mut memory = Memory::new();
+    memory.screen_mut()[0] = 0b1010;
+    memory.screen_mut()[100] = 0xFFFF;
+    save_screenshot(&path("expected.png"), &memory, Palette::OFFICIAL).unwrap();
+
+    let assertion = ScreenAssertion::new(&path("expected.png"), None, path("diff.png")).unwrap();
+    assert_eq!(assertion.mismatches(&memory), 0);
+    assertion.check(&memory).unwrap();
+    assert!(!dir.join("diff.png").exists());
+
+    // One pixel missing and two extra
+    let mut changed = Memory::new();
+    changed.screen_mut()[0] = 0b0011;
+    changed.screen_mut()[100] = 0xFFFF;
+    changed.screen_mut()[8191] = 0x8000;
+    assert_eq!(assertion.mismatches(&changed), 3);
+    assert!(matches!(assertion.check(&changed), Err(Error::ScreenMismatch(_))));
+    assert!(dir.join("diff.png").exists());
+
+    // Masking the first word leaves the last pixel of the screen
+    let mut mask = Memory::new();
+    mask.screen_mut()[0] = 0xFFFF;
+    save_screenshot(&path("mask.png"), &mask, Palette::OFFICIAL).unwrap();
+    let masked = ScreenAssertion::new(&path("expected.png"), Some(&path("mask.png")), path("diff.png")).unwrap();
+    assert_eq!(masked.mismatches(&changed), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn screen_assertion_rejects_images_of_another_size() {
+    let dir = test_dir("size");
+    let path = dir.join("small.png");
+    let mut encoder = png::Encoder::new(fs::File::create(&path).unwrap(), 256, 256);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.write_header().unwrap().write_image_data(&[0; 256 * 256]).unwrap();
+
+    let result = ScreenAssertion::new(&path.to_string_lossy(), None, String::new());
+    assert!(matches!(result, Err(Error::ImageError(_))));
+    fs::remove_dir_all(&dir).unwrap();
+}
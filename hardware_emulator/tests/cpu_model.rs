@@ -0,0 +1,67 @@
+use hardware_emulator::{Emulator, Rom, Error, CpuModelKind};
+
+// Programs run with every CPU model, which must stop the way the reference CPU does
+
+const MODELS: [&str; 6] = ["reference", "single-cycle", "pipeline:2", "pipeline:3", "pipeline:4", "pipeline:5"];
+
+fn rom(instructions: &[u16]) -> Rom {
+    Rom::from_words(instructions).unwrap()
+}
+
+fn run(rom: &Rom, model: &str, cycles: u64) -> (Emulator, Result<(), Error>) {
+    let mut emulator = Emulator::new(rom.clone(), None, String::new());
+    emulator.max_cycles = Some(cycles);
+    if model != "reference" {
+        emulator.cpu_model = Some(model.parse::<CpuModelKind>().unwrap().create().unwrap());
+    }
+    let result = emulator.run_to_end();
+    (emulator, result)
+}
+
+#[test]
+fn write_past_the_bus_is_an_invalid_address() {
+    // @0, A=A-1, M=1: writes to 0xFFFF
+    let rom = rom(&[0x0000, 0xECA0, 0xEFC8]);
+    for model in MODELS {
+        let (_, result) = run(&rom, model, 10);
+        assert!(matches!(result, Err(Error::InvalidMemoryAddress(0xFFFF))), "{}: {:?}", model, result);
+    }
+}
+
+#[test]
+fn read_past_the_bus_is_an_invalid_address() {
+    // @0, A=A-1, D=M: reads 0xFFFF
+    let rom = rom(&[0x0000, 0xECA0, 0xFC10]);
+    for model in MODELS {
+        let (_, result) = run(&rom, model, 10);
+        assert!(matches!(result, Err(Error::InvalidMemoryAddress(0xFFFF))), "{}: {:?}", model, result);
+    }
+}
+
+#[test]
+fn models_end_in_the_reference_state() {
+    // RAM[0] = 0, then RAM[0] += 1 in a loop: @0, M=0, (LOOP) @0, M=M+1, @2, 0;JMP
+    let rom = rom(&[0x0000, 0xEA88, 0x0000, 0xFDC8, 0x0002, 0xEA87]);
+    let (reference, _) = run(&rom, "reference", 10_000);
+    for model in MODELS {
+        let (emulator, result) = run(&rom, model, 10_000);
+        assert!(result.is_ok(), "{}: {:?}", model, result);
+        assert_eq!((emulator.cpu.pc, emulator.cpu.reg_a, emulator.cpu.reg_d), (reference.cpu.pc, reference.cpu.reg_a, reference.cpu.reg_d), "{}", model);
+        assert_eq!(emulator.memory.data_words(), reference.memory.data_words(), "{}", model);
+    }
+    assert_eq!(reference.memory.words[0], 2500);
+}
+
+#[test]
+fn unsupported_pipeline_depth_is_an_error() {
+    assert!(matches!(CpuModelKind::Pipeline(7).create(), Err(Error::ConfigError(_))));
+}
+
+#[test]
+fn models_count_their_own_cycles() {
+    let rom = rom(&[0x0000, 0xEA88, 0x0000, 0xFDC8, 0x0002, 0xEA87]);
+    let (single_cycle, _) = run(&rom, "single-cycle", 1000);
+    assert_eq!(single_cycle.cpu_model.unwrap().cycles(), 1000);
+    let (pipeline, _) = run(&rom, "pipeline:5", 1000);
+    assert!(pipeline.cpu_model.unwrap().cycles() > 1000);
+}
@@ -0,0 +1,83 @@
+use std::{env, fs, path::PathBuf};
+
+use hardware_emulator::{Device, Disk, DiskImage, Error};
+
+// Disk commands written to the device registers, as a program does, on an image in a temporary directory
+
+const SECTOR: usize = 0;
+const COMMAND: usize = 1;
+const STATUS: usize = 2;
+const SECTORS: usize = 3;
+const BUFFER: usize = 4;
+
+fn image(name: &str, sectors: u64) -> (PathBuf, String) {
+    let dir = env::temp_dir().join(format!("hack_disk_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("disk.img").to_string_lossy().into_owned();
+    let _ = fs::remove_file(&path);
+    DiskImage::create(&path, sectors).unwrap();
+    (dir, path)
+}
+
+fn command(disk: &mut Disk, sector: u16, command: u16) -> u16 {
+    disk.write(SECTOR, sector, 0);
+    disk.write(COMMAND, command, 0);
+    disk.read(STATUS, 0)
+}
+
+#[test]
+fn written_sector_is_read_back() {
+    let (dir, path) = image("write", 4);
+    let mut disk = Disk::new(Some(&path)).unwrap();
+    assert_eq!(disk.read(SECTORS, 0), 4);
+
+    for offset in 0..DiskImage::SECTOR_WORDS {
+        disk.write(BUFFER + offset, offset as u16 * 3, 0);
+    }
+    assert_eq!(command(&mut disk, 2, Disk::WRITE), Disk::STATUS_OK);
+    assert_eq!(disk.read(COMMAND, 0), Disk::WRITE);
+
+    // Reading another sector replaces the buffer, reading the written one brings it back
+    assert_eq!(command(&mut disk, 1, Disk::READ), Disk::STATUS_OK);
+    assert_eq!(disk.read(BUFFER + 10, 0), 0);
+    assert_eq!(command(&mut disk, 2, Disk::READ), Disk::STATUS_OK);
+    assert_eq!(disk.read(BUFFER + 10, 0), 30);
+    assert_eq!(disk.read(BUFFER + 255, 0), 765);
+
+    // The write reached the image file
+    let mut words = [0; DiskImage::SECTOR_WORDS];
+    DiskImage::open(&path).unwrap().read_sector(2, &mut words).unwrap();
+    assert_eq!(words[10], 30);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_commands_leave_the_buffer_unchanged() {
+    let (dir, path) = image("invalid", 2);
+    let mut disk = Disk::new(Some(&path)).unwrap();
+    disk.write(BUFFER, 0xBEEF, 0);
+
+    assert_eq!(command(&mut disk, 2, Disk::READ), Disk::STATUS_INVALID_SECTOR);
+    assert_eq!(command(&mut disk, 0, 7), Disk::STATUS_INVALID_COMMAND);
+    assert_eq!(disk.read(BUFFER, 0), 0xBEEF);
+
+    // The status is the one of the last command, and read-only
+    assert_eq!(command(&mut disk, 0, Disk::READ), Disk::STATUS_OK);
+    disk.write(STATUS, Disk::STATUS_IO_ERROR, 0);
+    disk.write(SECTORS, 100, 0);
+    assert_eq!((disk.read(STATUS, 0), disk.read(SECTORS, 0)), (Disk::STATUS_OK, 2));
+    assert_eq!(disk.read(BUFFER, 0), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn disk_needs_a_valid_image() {
+    let (dir, path) = image("open", 1);
+    assert!(matches!(Disk::new(None), Err(Error::DeviceError(_))));
+    assert!(DiskImage::create(&path, 1).is_err());
+
+    fs::write(&path, [0; 3]).unwrap();
+    assert!(matches!(Disk::new(Some(&path)), Err(Error::DeviceError(_))));
+    assert!(matches!(Disk::new(Some(&dir.join("missing.img").to_string_lossy())), Err(Error::DeviceError(_))));
+    fs::remove_dir_all(&dir).unwrap();
+}
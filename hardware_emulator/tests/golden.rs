@@ -0,0 +1,36 @@
+use std::env;
+
+use hardware_emulator::{Emulator, Rom, ScreenAssertion};
+
+// Screens of the example programs after a headless run, checked against reference images
+
+fn example(name: &str) -> String {
+    format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn golden(name: &str) -> String {
+    format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn assert_screen(program: &str, cycles: u64, fast: bool, expected: &str) {
+    let mut emulator = Emulator::new(Rom::from_file(&example(program)).unwrap(), None, String::new());
+    emulator.max_cycles = Some(cycles);
+    if fast {
+        emulator.enable_fast_engine();
+    }
+    emulator.run_to_end().unwrap();
+
+    let diff_path = env::temp_dir().join(format!("{}.diff.png", expected)).to_string_lossy().into_owned();
+    let assertion = ScreenAssertion::new(&golden(expected), None, diff_path).unwrap();
+    assertion.check(&emulator.memory).unwrap();
+}
+
+#[test]
+fn pong_after_10m_cycles() {
+    assert_screen("Pong.hack", 10_000_000, false, "pong_10m.png");
+}
+
+#[test]
+fn pong_after_10m_cycles_fast_engine() {
+    assert_screen("Pong.hack", 10_000_000, true, "pong_10m.png");
+}
@@ -0,0 +1,73 @@
+use hardware_emulator::{InputScript, InputRecorder, Memory, Error, Emulator, Rom};
+
+// Input scripts parsed from text and replayed on the keyboard register
+
+fn keyboard(memory: &Memory) -> u16 {
+    memory.data_words()[Memory::KEYBOARD_REG_OFFSET]
+}
+
+// Keyboard register at each of the cycles, applying the script as the emulator does
+fn replay(script: &mut InputScript, cycles: &[u64]) -> Vec<u16> {
+    let mut memory = Memory::new();
+    cycles.iter().map(|cycle| {
+        script.apply(&mut memory, *cycle);
+        keyboard(&memory)
+    }).collect()
+}
+
+#[test]
+fn statements_by_line_or_separated_by_semicolons() {
+    let text = "
+        # Press and release Up
+        at cycle 1000 set KBD=131
+        at   cycle   2000   set KBD = 0   # Released
+        at cycle 3000 set KBD=65; at cycle 3000 set KBD=66;
+    ";
+    let mut script = InputScript::parse("test", text).unwrap();
+    assert_eq!(script.next_cycle(), Some(1000));
+    assert_eq!(replay(&mut script, &[0, 999, 1000, 1999, 2000, 3000]), [0, 0, 131, 131, 0, 66]);
+    assert_eq!(script.next_cycle(), None);
+}
+
+#[test]
+fn late_apply_catches_up_in_order() {
+    let mut script = InputScript::parse("test", "at cycle 10 set KBD=1; at cycle 20 set KBD=2").unwrap();
+    assert_eq!(replay(&mut script, &[25]), [2]);
+}
+
+#[test]
+fn invalid_statements_are_errors() {
+    for text in ["at cycle 10", "at cycle ten set KBD=1", "at cycle 10 set KBD=65536", "at cycle 10 set A=1", "set KBD=1", "at cycle 10 set KBD=-1"] {
+        assert!(matches!(InputScript::parse("test", text), Err(Error::InputScriptError(_))), "{}", text);
+    }
+    match InputScript::parse("keys.txt", "# Header\nat cycle 10 set KBD=1\nat cycle 5 set KBD=1") {
+        Err(Error::InputScriptError(message)) => assert!(message.starts_with("keys.txt:3: cycles must not decrease"), "{}", message),
+        _ => panic!("out of order cycles accepted"),
+    }
+}
+
+#[test]
+fn recorded_input_is_replayed_exactly() {
+    let mut recorder = InputRecorder::new(String::new());
+    for (cycle, value) in [(100, 65), (150, 65), (200, 0), (200, 66), (5000, 0)] {
+        recorder.record(cycle, value);
+    }
+    let script = recorder.script();
+    assert_eq!(script.lines().filter(|line| line.starts_with("at")).count(), 4);
+
+    let mut replayed = InputScript::parse("recorded", &script).unwrap();
+    assert_eq!(replay(&mut replayed, &[99, 100, 199, 200, 4999, 5000]), [0, 65, 65, 66, 66, 0]);
+}
+
+#[test]
+fn emulator_stops_at_each_scripted_cycle() {
+    // (LOOP) @LOOP, 0;JMP
+    let mut emulator = Emulator::new(Rom::from_words(&[0x0000, 0xEA87]).unwrap(), None, String::new());
+    emulator.input_script = Some(InputScript::parse("test", "at cycle 7 set KBD=65; at cycle 9 set KBD=0").unwrap());
+    emulator.step(100).unwrap();
+    assert_eq!((emulator.cycles, keyboard(&emulator.memory)), (7, 0));
+    emulator.step(100).unwrap();
+    assert_eq!((emulator.cycles, keyboard(&emulator.memory)), (9, 65));
+    emulator.step(100).unwrap();
+    assert_eq!((emulator.cycles, keyboard(&emulator.memory)), (109, 0));
+}
@@ -0,0 +1,127 @@
+use hardware_emulator::{Keyboard, KeyEvent};
+use winit::event::{VirtualKeyCode, ModifiersState};
+
+// Window keyboard events fed through Keyboard::handle_event, as the emulator does
+
+const A: u32 = 30;
+const B: u32 = 48;
+const Q: u32 = 16;
+const S: u32 = 31;
+const LEFT: u32 = 105;
+const CTRL: u32 = 29;
+
+fn none() -> ModifiersState {
+    ModifiersState::empty()
+}
+
+fn ctrl() -> ModifiersState {
+    ModifiersState::CTRL
+}
+
+// A key producing text: its press, then the character received for it
+fn type_key(keyboard: &mut Keyboard, scancode: u32, key: VirtualKeyCode, character: char, modifiers: ModifiersState) {
+    assert_eq!(keyboard.handle_event(KeyEvent::Pressed(scancode, Some(key)), modifiers), None);
+    assert_eq!(keyboard.handle_event(KeyEvent::Character(character), modifiers), None);
+}
+
+fn release(keyboard: &mut Keyboard, scancode: u32, modifiers: ModifiersState) {
+    assert_eq!(keyboard.handle_event(KeyEvent::Released(scancode), modifiers), None);
+}
+
+#[test]
+fn text_key_gives_its_character() {
+    let mut keyboard = Keyboard::default();
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'A', ModifiersState::SHIFT);
+    assert_eq!(keyboard.reg, 65);
+    release(&mut keyboard, A, ModifiersState::SHIFT);
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn special_key_gives_its_code() {
+    let mut keyboard = Keyboard::default();
+    keyboard.handle_event(KeyEvent::Pressed(LEFT, Some(VirtualKeyCode::Left)), none());
+    assert_eq!(keyboard.reg, Keyboard::LEFT_ARROW);
+    release(&mut keyboard, LEFT, none());
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn overlapping_presses_give_the_last_key_held() {
+    let mut keyboard = Keyboard::default();
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'a', none());
+    type_key(&mut keyboard, B, VirtualKeyCode::B, 'b', none());
+    assert_eq!(keyboard.reg, 'b' as u16);
+    release(&mut keyboard, B, none());
+    assert_eq!(keyboard.reg, 'a' as u16);
+    release(&mut keyboard, A, none());
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn releasing_an_earlier_key_keeps_the_last_one() {
+    let mut keyboard = Keyboard::default();
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'a', none());
+    keyboard.handle_event(KeyEvent::Pressed(LEFT, Some(VirtualKeyCode::Left)), none());
+    release(&mut keyboard, A, none());
+    assert_eq!(keyboard.reg, Keyboard::LEFT_ARROW);
+    release(&mut keyboard, LEFT, none());
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn key_repeat_makes_the_key_the_last_one_once() {
+    let mut keyboard = Keyboard::default();
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'a', none());
+    type_key(&mut keyboard, B, VirtualKeyCode::B, 'b', none());
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'a', none());
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'a', none());
+    assert_eq!(keyboard.reg, 'a' as u16);
+    release(&mut keyboard, A, none());
+    assert_eq!(keyboard.reg, 'b' as u16);
+    release(&mut keyboard, B, none());
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn key_released_while_ctrl_is_held_is_released() {
+    let mut keyboard = Keyboard::default();
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'a', none());
+    assert_eq!(keyboard.handle_event(KeyEvent::Pressed(CTRL, Some(VirtualKeyCode::LControl)), ctrl()), Some(VirtualKeyCode::LControl));
+    assert_eq!(keyboard.reg, 'a' as u16);
+    release(&mut keyboard, A, ctrl());
+    assert_eq!(keyboard.reg, 0);
+    release(&mut keyboard, CTRL, none());
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn ctrl_key_is_a_hotkey() {
+    let mut keyboard = Keyboard::default();
+    assert_eq!(keyboard.handle_event(KeyEvent::Pressed(S, Some(VirtualKeyCode::S)), ctrl()), Some(VirtualKeyCode::S));
+    assert_eq!(keyboard.handle_event(KeyEvent::Character('\u{13}'), ctrl()), None);
+    assert_eq!(keyboard.reg, 0);
+    release(&mut keyboard, S, ctrl());
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn altgr_reported_as_ctrl_alt_types_text() {
+    let mut keyboard = Keyboard::default();
+    let altgr = ModifiersState::CTRL | ModifiersState::ALT;
+    type_key(&mut keyboard, Q, VirtualKeyCode::Q, '@', altgr);
+    assert_eq!(keyboard.reg, '@' as u16);
+    release(&mut keyboard, Q, altgr);
+    assert_eq!(keyboard.reg, 0);
+}
+
+#[test]
+fn losing_the_focus_releases_every_key() {
+    let mut keyboard = Keyboard::default();
+    type_key(&mut keyboard, A, VirtualKeyCode::A, 'a', none());
+    type_key(&mut keyboard, B, VirtualKeyCode::B, 'b', none());
+    keyboard.handle_event(KeyEvent::FocusLost, none());
+    assert_eq!(keyboard.reg, 0);
+    release(&mut keyboard, B, none());
+    assert_eq!(keyboard.reg, 0);
+}
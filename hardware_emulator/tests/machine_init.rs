@@ -0,0 +1,42 @@
+use hardware_emulator::{Emulator, Rom, MachineInit};
+
+// Machine state at start and after the reset hotkey, for the machine initialization settings
+
+// RAM[100] = 1, SCREEN[0] = 1, then loop: @100, M=1, @SCREEN, M=1, (LOOP) @4, 0;JMP
+const PROGRAM: [u16; 6] = [100, 0xEFC8, 0x4000, 0xEFC8, 4, 0xEA87];
+
+fn emulator(settings: &[&str]) -> Emulator {
+    let mut init = MachineInit::default();
+    for setting in settings {
+        init.set_arg(setting).unwrap();
+    }
+    let mut emulator = Emulator::new(Rom::from_words(&PROGRAM).unwrap(), None, String::new());
+    emulator.set_init(init).unwrap();
+    emulator
+}
+
+#[test]
+fn stack_pointer_starts_at_0_by_default() {
+    // RAM[0] was set to 2000 at start before machine initialization settings existed
+    let emulator = emulator(&[]);
+    assert_eq!(emulator.memory.words[0], 0);
+    assert_eq!(emulator.cpu.pc, 0);
+}
+
+#[test]
+fn vm_bootstrap_sets_the_segment_pointers() {
+    let emulator = emulator(&["preset=vm-bootstrap", "SP=261"]);
+    assert_eq!(emulator.memory.words[..5], [261, 300, 400, 3000, 3010]);
+}
+
+#[test]
+fn reset_clears_what_its_mode_says() {
+    for (mode, ram, screen) in [("pc", 1, 1), ("ram", 0, 1), ("all", 0, 0)] {
+        let mut emulator = emulator(&[&format!("reset={}", mode), "SP=256", "PC=0"]);
+        emulator.step(10).unwrap();
+        emulator.reset().unwrap();
+        assert_eq!(emulator.cpu.pc, 0, "{}", mode);
+        assert_eq!(emulator.memory.words[0], 256, "{}", mode);
+        assert_eq!((emulator.memory.words[100], emulator.memory.screen()[0]), (ram, screen), "{}", mode);
+    }
+}
@@ -0,0 +1,80 @@
+use std::{env, fs, path::PathBuf};
+
+use hardware_emulator::{Snapshot, Cpu, Memory, Error, Emulator, Rom};
+
+// Snapshots saved and loaded through files in a temporary directory
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hack_state_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn machine() -> (Cpu, Memory) {
+    let mut cpu = Cpu::new();
+    (cpu.reg_a, cpu.reg_d, cpu.pc) = (0x1234, 0xFFFF, 0x7FFF);
+    let mut memory = Memory::new();
+    memory.words[0] = 256;
+    memory.words[Memory::RAM_SIZE - 1] = 1;
+    memory.screen_mut()[100] = 0xAAAA;
+    memory.set_keyboard_reg(65);
+    (cpu, memory)
+}
+
+#[test]
+fn saved_state_is_restored() {
+    let dir = test_dir("round_trip");
+    let path = dir.join("machine.state").to_string_lossy().into_owned();
+    let (cpu, memory) = machine();
+    let snapshot = Snapshot::capture(&cpu, &memory);
+    snapshot.save(&path).unwrap();
+
+    let loaded = Snapshot::load(&path).unwrap();
+    assert!(loaded == snapshot);
+    let (mut restored_cpu, mut restored_memory) = (Cpu::new(), Memory::new());
+    loaded.restore(&mut restored_cpu, &mut restored_memory);
+    assert_eq!((restored_cpu.reg_a, restored_cpu.reg_d, restored_cpu.pc), (0x1234, 0xFFFF, 0x7FFF));
+    assert_eq!(restored_memory.data_words(), memory.data_words());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn emulator_state_is_restored() {
+    let dir = test_dir("emulator");
+    let path = dir.join("emulator.state").to_string_lossy().into_owned();
+
+    // RAM[0] += 1 in a loop: (LOOP) @0, M=M+1, @0, 0;JMP
+    let rom = Rom::from_words(&[0x0000, 0xFDC8, 0x0000, 0xEA87]).unwrap();
+    let mut emulator = Emulator::new(rom, None, path.clone());
+    emulator.step(401).unwrap();
+    emulator.save_state(&path).unwrap();
+    emulator.step(1000).unwrap();
+    emulator.load_state(&path).unwrap();
+    assert_eq!((emulator.memory.words[0], emulator.cpu.pc), (100, 1));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_files_are_errors() {
+    let dir = test_dir("invalid");
+    let path = dir.join("machine.state").to_string_lossy().into_owned();
+    let (cpu, memory) = machine();
+    Snapshot::capture(&cpu, &memory).save(&path).unwrap();
+    let valid = fs::read(&path).unwrap();
+
+    let mut wrong_magic = valid.clone();
+    wrong_magic[0] = b'X';
+    let mut wrong_version = valid.clone();
+    wrong_version[9] = 2;
+    let mut wrong_length = valid.clone();
+    wrong_length[21] ^= 1;
+    let mut trailing = valid.clone();
+    trailing.push(0);
+    for bytes in [&valid[..10], &valid[..valid.len() - 1], &valid[..valid.len() - 2], &wrong_magic, &wrong_version, &wrong_length, &trailing, &[]] {
+        fs::write(&path, bytes).unwrap();
+        assert!(matches!(Snapshot::load(&path), Err(Error::StateError(_))), "{} bytes", bytes.len());
+    }
+
+    assert!(matches!(Snapshot::load(&dir.join("missing.state").to_string_lossy()), Err(Error::IoError(_))));
+    fs::remove_dir_all(&dir).unwrap();
+}
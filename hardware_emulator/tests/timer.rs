@@ -0,0 +1,63 @@
+use hardware_emulator::{Device, Timer};
+
+// Timer registers read and written at given cycles, as the bus does
+
+const CYCLES_LOW: usize = 0;
+const CYCLES_HIGH: usize = 1;
+const MILLISECONDS_LOW: usize = 2;
+const MILLISECONDS_HIGH: usize = 3;
+const COUNTDOWN: usize = 4;
+
+#[test]
+fn cycle_counter_latches_its_high_bits() {
+    let mut timer = Timer::new(1_000_000);
+    assert_eq!(timer.read(CYCLES_HIGH, 0x0012_3456), 0);
+    assert_eq!(timer.read(CYCLES_LOW, 0x0012_3456), 0x3456);
+    assert_eq!(timer.read(CYCLES_HIGH, 0x0013_0000), 0x0012);
+    assert_eq!(timer.read(CYCLES_LOW, 0x0013_0000), 0);
+    assert_eq!(timer.read(CYCLES_HIGH, 0x0013_0000), 0x0013);
+}
+
+#[test]
+fn millisecond_counter_latches_its_high_bits() {
+    let mut timer = Timer::new(1_000_000);
+    assert!(timer.read(MILLISECONDS_LOW, 0) < 1000);
+    assert_eq!(timer.read(MILLISECONDS_HIGH, 0), 0);
+}
+
+#[test]
+fn countdown_counts_emulated_milliseconds() {
+    // 2 cycles per millisecond
+    let mut timer = Timer::new(2000);
+    assert_eq!(timer.read(COUNTDOWN, 0), 0);
+    timer.write(COUNTDOWN, 10, 100);
+    assert_eq!(timer.read(COUNTDOWN, 100), 10);
+    assert_eq!(timer.read(COUNTDOWN, 101), 10);
+    assert_eq!(timer.read(COUNTDOWN, 102), 9);
+    assert_eq!(timer.read(COUNTDOWN, 119), 1);
+    assert_eq!(timer.read(COUNTDOWN, 120), 0);
+    assert_eq!(timer.read(COUNTDOWN, 1000), 0);
+
+    // Writing again restarts the wait, 0 stops it
+    timer.write(COUNTDOWN, 5, 1000);
+    assert_eq!(timer.read(COUNTDOWN, 1004), 3);
+    timer.write(COUNTDOWN, 0, 1004);
+    assert_eq!(timer.read(COUNTDOWN, 1004), 0);
+}
+
+#[test]
+fn countdown_with_a_clock_below_1_khz() {
+    let mut timer = Timer::new(100);
+    timer.write(COUNTDOWN, 3, 0);
+    assert_eq!(timer.read(COUNTDOWN, 2), 1);
+    assert_eq!(timer.read(COUNTDOWN, 3), 0);
+}
+
+#[test]
+fn other_registers_ignore_writes() {
+    let mut timer = Timer::new(1000);
+    timer.write(CYCLES_LOW, 5, 0);
+    timer.write(CYCLES_HIGH, 5, 0);
+    assert_eq!(timer.read(CYCLES_LOW, 7), 7);
+    assert_eq!(timer.read(CYCLES_HIGH, 7), 0);
+}
@@ -0,0 +1,60 @@
+use std::{env, fs, process::Command};
+
+use hardware_emulator::{verify_translation, Emulator, Rom, Translator};
+
+// Programs translated to Rust and compiled with rustc, which must end in the interpreter's state
+
+fn emulator(program: &[u16]) -> Emulator {
+    Emulator::new(Rom::from_words(program).unwrap(), None, String::new())
+}
+
+#[test]
+fn loop_ends_like_the_interpreter() {
+    // RAM[0] = 0, then RAM[0] += 1 in a loop: @0, M=0, (LOOP) @0, M=M+1, @2, 0;JMP
+    verify_translation(emulator(&[0x0000, 0xEA88, 0x0000, 0xFDC8, 0x0002, 0xEA87]), 10_000).unwrap();
+}
+
+#[test]
+fn conditional_and_computed_jumps_end_like_the_interpreter() {
+    // @10, D=A, @4, D;JGT, @0, M=M+1, @0, D=M, @12, A=D+A, 0;JMP: then runs the empty ROM
+    let program = [10, 0xEC10, 4, 0xE301, 0, 0xFDC8, 0, 0xFC10, 12, 0xE0A0, 0xEA87];
+    verify_translation(emulator(&program), 10_000).unwrap();
+}
+
+#[test]
+fn invalid_address_stops_like_the_interpreter() {
+    // @1, D=A, @0x6001, M=D
+    verify_translation(emulator(&[1, 0xEC10, 0x6001, 0xE308]), 100).unwrap();
+}
+
+#[test]
+fn translated_program_rejects_invalid_states() {
+    let dir = env::temp_dir().join(format!("hack_translator_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+    Translator::new(&emulator(&[0x0000, 0xEA87])).write(&path("translated.rs")).unwrap();
+    assert!(Command::new("rustc").args(["-o", &path("translated"), &path("translated.rs")]).status().unwrap().success());
+    let run = |state: &[u8]| {
+        fs::write(path("input.state"), state).unwrap();
+        Command::new(path("translated")).args(["10", &path("output.state"), &path("input.state")]).output().unwrap()
+    };
+
+    // A state saved by the program itself is accepted
+    assert!(Command::new(path("translated")).args(["10", &path("valid.state")]).status().unwrap().success());
+    let valid = fs::read(path("valid.state")).unwrap();
+    assert!(run(&valid).status.success());
+
+    let mut wrong_version = valid.clone();
+    wrong_version[9] = 2;
+    let mut wrong_length = valid.clone();
+    wrong_length[21] ^= 1;
+    let truncated = &valid[..valid.len() - 2];
+    for (state, message) in [(&wrong_version[..], "Unsupported snapshot version 2"), (&wrong_length[..], "memory words"),
+        (truncated, "Truncated snapshot"), (b"HACK", "not a Hack snapshot")] {
+        let output = run(state);
+        assert_eq!(output.status.code(), Some(2), "{}", message);
+        assert!(String::from_utf8_lossy(&output.stdout).contains(message), "{}", message);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}